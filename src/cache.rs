@@ -0,0 +1,335 @@
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Objects of one kind in one namespace, kept current by a watch.
+struct CachedList {
+    objects: BTreeMap<String, Value>,
+    /// Sizes of the manifests served for the objects, by name
+    sizes: HashMap<String, RenderedSize>,
+    resource_version: String,
+    last_used: Instant,
+    /// Identifies the watch feeding this list, so a watch which outlived a
+    /// relist stops instead of applying events to the new one.
+    generation: u64,
+}
+
+/// The size of an object as rendered by one view at one resource version.
+struct RenderedSize {
+    resource_version: String,
+    view: u64,
+    size: u64,
+}
+
+struct Lists {
+    next_generation: u64,
+    lists: HashMap<String, CachedList>,
+}
+
+/// Informer-style cache of listings, keyed by the URL a kind is listed from.
+/// A listing is filled by a list and then kept current by a watch running on
+/// the client's runtime. Listings unused for longer than the TTL are evicted
+/// and their watch stops; a TTL of zero disables caching.
+#[derive(Clone)]
+pub struct ObjectCache {
+    lists: Arc<Mutex<Lists>>,
+    ttl: Duration,
+}
+
+impl ObjectCache {
+    pub fn new(ttl: Duration) -> Self {
+        ObjectCache {
+            lists: Arc::new(Mutex::new(Lists {
+                next_generation: 1,
+                lists: HashMap::new(),
+            })),
+            ttl,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.ttl > Duration::from_secs(0)
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lists> {
+        // A watch panicking mid-update leaves nothing half-written worth
+        // refusing to read
+        self.lists.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns every object of a cached listing, evicting listings which
+    /// have gone unused for longer than the TTL.
+    pub fn list(&self, url: &str) -> Option<Vec<Value>> {
+        let ttl = self.ttl;
+        let mut lists = self.lock();
+        lists.lists.retain(|_, list| list.last_used.elapsed() < ttl);
+
+        let list = lists.lists.get_mut(url)?;
+        list.last_used = Instant::now();
+
+        Some(list.objects.values().cloned().collect())
+    }
+
+    pub fn get(&self, url: &str, name: &str) -> Option<Value> {
+        let mut lists = self.lock();
+        let list = lists.lists.get_mut(url)?;
+        if list.last_used.elapsed() >= self.ttl {
+            return None;
+        }
+        list.last_used = Instant::now();
+
+        list.objects.get(name).cloned()
+    }
+
+    /// Stores the result of a list and returns the generation the watch
+    /// continuing from it must pass to `apply`.
+    pub fn insert_list(&self, url: &str, items: Vec<Value>, resource_version: &str) -> u64 {
+        let mut lists = self.lock();
+        let generation = lists.next_generation;
+        lists.next_generation += 1;
+
+        let objects = items
+            .into_iter()
+            .filter_map(|o| Some((o["metadata"]["name"].as_str()?.to_string(), o)))
+            .collect();
+
+        // Sizes are kept by version, so those of a previous listing still hold
+        let sizes = lists
+            .lists
+            .remove(url)
+            .map(|list| list.sizes)
+            .unwrap_or_default();
+
+        lists.lists.insert(
+            url.to_string(),
+            CachedList {
+                objects,
+                sizes,
+                resource_version: resource_version.to_string(),
+                last_used: Instant::now(),
+                generation,
+            },
+        );
+
+        generation
+    }
+
+    /// Resource version a watch has to resume from, or `None` once the
+    /// listing was evicted or replaced and the watch should stop.
+    pub fn resume_from(&self, url: &str, generation: u64) -> Option<String> {
+        let lists = self.lock();
+        let list = lists.lists.get(url)?;
+
+        if list.generation != generation || list.last_used.elapsed() >= self.ttl {
+            return None;
+        }
+
+        Some(list.resource_version.clone())
+    }
+
+    /// Applies one watch event. Returns `false` when the watch has to stop,
+    /// either because the listing is gone or because the server reported an
+    /// error such as an expired resource version, in which case the listing
+    /// is dropped and fetched again on next use.
+    pub fn apply(&self, url: &str, generation: u64, event: &Value) -> bool {
+        let mut lists = self.lock();
+        let list = match lists.lists.get_mut(url) {
+            Some(list) if list.generation == generation => list,
+            _ => return false,
+        };
+
+        let object = &event["object"];
+        let name = object["metadata"]["name"].as_str().map(String::from);
+        if let Some(version) = object["metadata"]["resourceVersion"].as_str() {
+            list.resource_version = version.to_string();
+        }
+
+        match (event["type"].as_str(), name) {
+            (Some("ADDED"), Some(name)) | (Some("MODIFIED"), Some(name)) => {
+                list.objects.insert(name, object.clone());
+            }
+            (Some("DELETED"), Some(name)) => {
+                list.objects.remove(&name);
+                list.sizes.remove(&name);
+            }
+            (Some("BOOKMARK"), _) => {}
+            _ => {
+                lists.lists.remove(url);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Stores an object returned by a write to the API server, so the change
+    /// shows up before its watch event arrives.
+    pub fn upsert(&self, url: &str, object: &Value) {
+        let mut lists = self.lock();
+
+        if let (Some(list), Some(name)) = (
+            lists.lists.get_mut(url),
+            object["metadata"]["name"].as_str(),
+        ) {
+            list.objects.insert(name.to_string(), object.clone());
+        }
+    }
+
+    /// Size stored with `set_size` for an object, as long as it was stored
+    /// for the same resource version and view.
+    pub fn size(&self, url: &str, name: &str, resource_version: &str, view: u64) -> Option<u64> {
+        let lists = self.lock();
+        let size = lists.lists.get(url)?.sizes.get(name)?;
+
+        if size.resource_version != resource_version || size.view != view {
+            return None;
+        }
+
+        Some(size.size)
+    }
+
+    /// Stores the size of the manifest rendered for an object of a cached
+    /// listing, so it is only rendered again once the object changes.
+    pub fn set_size(&self, url: &str, name: &str, resource_version: &str, view: u64, size: u64) {
+        let mut lists = self.lock();
+
+        if let Some(list) = lists.lists.get_mut(url) {
+            list.sizes.insert(
+                name.to_string(),
+                RenderedSize {
+                    resource_version: resource_version.to_string(),
+                    view,
+                    size,
+                },
+            );
+        }
+    }
+
+    pub fn remove(&self, url: &str, generation: u64) {
+        let mut lists = self.lock();
+
+        if lists.lists.get(url).map(|list| list.generation) == Some(generation) {
+            lists.lists.remove(url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const URL: &str = "/apis/apps/v1/namespaces/default/deployments";
+
+    fn object(name: &str, version: &str) -> Value {
+        json!({ "metadata": { "name": name, "resourceVersion": version } })
+    }
+
+    fn names(cache: &ObjectCache) -> Vec<String> {
+        cache
+            .list(URL)
+            .unwrap()
+            .iter()
+            .map(|o| o["metadata"]["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_watch_events_keep_listing_current() {
+        let cache = ObjectCache::new(Duration::from_secs(60));
+        let generation = cache.insert_list(URL, vec![object("web", "1"), object("db", "2")], "2");
+
+        assert!(cache.apply(
+            URL,
+            generation,
+            &json!({ "type": "ADDED", "object": object("cache", "3") })
+        ));
+        assert!(cache.apply(
+            URL,
+            generation,
+            &json!({ "type": "DELETED", "object": object("db", "4") })
+        ));
+        assert!(cache.apply(
+            URL,
+            generation,
+            &json!({ "type": "MODIFIED", "object": object("web", "5") })
+        ));
+
+        assert_eq!(names(&cache), vec!["cache", "web"]);
+        assert_eq!(
+            cache.get(URL, "web").unwrap()["metadata"]["resourceVersion"],
+            "5"
+        );
+        assert_eq!(cache.resume_from(URL, generation).as_deref(), Some("5"));
+    }
+
+    #[test]
+    fn test_sizes_are_kept_until_objects_change() {
+        let cache = ObjectCache::new(Duration::from_secs(60));
+        cache.insert_list(URL, vec![object("web", "1")], "1");
+
+        cache.set_size(URL, "web", "1", 7, 120);
+        assert_eq!(cache.size(URL, "web", "1", 7), Some(120));
+        assert_eq!(cache.size(URL, "web", "1", 8), None);
+        assert_eq!(cache.size(URL, "web", "2", 7), None);
+
+        let generation = cache.insert_list(URL, vec![object("web", "1")], "1");
+        assert_eq!(cache.size(URL, "web", "1", 7), Some(120));
+
+        assert!(cache.apply(
+            URL,
+            generation,
+            &json!({ "type": "DELETED", "object": object("web", "2") })
+        ));
+        assert_eq!(cache.size(URL, "web", "1", 7), None);
+    }
+
+    #[test]
+    fn test_watch_error_drops_listing() {
+        let cache = ObjectCache::new(Duration::from_secs(60));
+        let generation = cache.insert_list(URL, vec![object("web", "1")], "1");
+
+        assert!(!cache.apply(
+            URL,
+            generation,
+            &json!({ "type": "ERROR", "object": { "code": 410 } })
+        ));
+        assert!(cache.list(URL).is_none());
+    }
+
+    #[test]
+    fn test_relist_stops_previous_watch() {
+        let cache = ObjectCache::new(Duration::from_secs(60));
+        let old = cache.insert_list(URL, vec![object("web", "1")], "1");
+        let new = cache.insert_list(URL, vec![object("web", "1")], "1");
+
+        assert!(!cache.apply(
+            URL,
+            old,
+            &json!({ "type": "ADDED", "object": object("db", "2") })
+        ));
+        assert!(cache.resume_from(URL, old).is_none());
+
+        cache.remove(URL, old);
+        assert!(cache.resume_from(URL, new).is_some());
+        assert_eq!(names(&cache), vec!["web"]);
+    }
+
+    #[test]
+    fn test_unused_listings_are_evicted() {
+        let cache = ObjectCache::new(Duration::from_millis(10));
+        let generation = cache.insert_list(URL, vec![object("web", "1")], "1");
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(cache.list(URL).is_none());
+        assert!(cache.resume_from(URL, generation).is_none());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::neat::LAST_APPLIED_ANNOTATION;
+use serde_json::{json, Map, Value};
+
+pub const CONFIG_MAP_KIND: &str = "configmaps";
+
+pub const SECRET_KIND: &str = "secrets";
+
+/// Stands in for every Secret value when secrets are redacted.
+pub const REDACTED: &str = "****";
+
+/// How the values of Secrets are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum SecretView {
+    /// base64-encoded, as served by the API server
+    Encoded,
+    /// Masked, so they never show up in a terminal. So is the annotation
+    /// `kubectl apply` keeps a copy of the whole Secret in.
+    Redacted,
+    /// Decoded into `stringData`, except for values which are not UTF-8
+    Decoded,
+}
+
+impl std::str::FromStr for SecretView {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "encoded" => Ok(SecretView::Encoded),
+            "redacted" => Ok(SecretView::Redacted),
+            "decoded" => Ok(SecretView::Decoded),
+            _ => Err(anyhow::anyhow!("Unknown secret view {}", s)),
+        }
+    }
+}
+
+impl SecretView {
+    /// Rewrites the values of a Secret as served by the API server. Both
+    /// the manifest and the key files are served from the result.
+    pub fn apply(self, secret: &mut Value) {
+        match self {
+            SecretView::Encoded => {}
+            SecretView::Redacted => {
+                for field in &["data", "stringData"] {
+                    if let Some(values) = secret.get_mut(field).and_then(Value::as_object_mut) {
+                        for value in values.values_mut() {
+                            *value = Value::from(REDACTED);
+                        }
+                    }
+                }
+                if let Some(last_applied) = last_applied_mut(secret) {
+                    *last_applied = Value::from(REDACTED);
+                }
+            }
+            SecretView::Decoded => {
+                let data = match secret.get_mut("data").map(Value::take) {
+                    Some(Value::Object(data)) => data,
+                    _ => return,
+                };
+
+                let (mut encoded, mut decoded) = (Map::new(), Map::new());
+                for (key, value) in data {
+                    let text = value
+                        .as_str()
+                        .and_then(|value| base64::decode(value).ok())
+                        .and_then(|value| String::from_utf8(value).ok());
+
+                    match text {
+                        Some(text) => decoded.insert(key, Value::from(text)),
+                        None => encoded.insert(key, value),
+                    };
+                }
+
+                let object = match secret.as_object_mut() {
+                    Some(object) => object,
+                    None => return,
+                };
+                object.remove("data");
+                if !encoded.is_empty() {
+                    object.insert(String::from("data"), Value::Object(encoded));
+                }
+                if !decoded.is_empty() {
+                    object.insert(String::from("stringData"), Value::Object(decoded));
+                }
+            }
+        }
+    }
+}
+
+/// The annotation `kubectl apply` keeps the applied manifest in, if set.
+fn last_applied_mut(o: &mut Value) -> Option<&mut Value> {
+    o.get_mut("metadata")?
+        .get_mut("annotations")?
+        .get_mut(LAST_APPLIED_ANNOTATION)
+}
+
+/// Puts the values of a Secret back where an edited copy of its redacted
+/// manifest still holds the mask, so saving an edit keeps them.
+pub fn restore_redacted(edited: &mut Value, current: &Value) -> anyhow::Result<()> {
+    if let Some(last_applied) = last_applied_mut(edited).filter(|value| *value == REDACTED) {
+        *last_applied = match &current["metadata"]["annotations"][LAST_APPLIED_ANNOTATION] {
+            Value::Null => anyhow::bail!(
+                "{} is redacted and has no value to keep",
+                LAST_APPLIED_ANNOTATION
+            ),
+            stored => stored.clone(),
+        };
+    }
+
+    for field in &["data", "stringData"] {
+        if let Some(values) = edited.get_mut(field).and_then(Value::as_object_mut) {
+            for (key, value) in values.iter_mut() {
+                if value != REDACTED {
+                    continue;
+                }
+
+                // Only data is ever stored, stringData is merged into it
+                *value = match current["data"][key].as_str() {
+                    Some(stored) if *field == "data" => Value::from(stored),
+                    Some(stored) => Value::from(String::from_utf8(base64::decode(stored)?)?),
+                    None => anyhow::bail!("{} is redacted and has no value to keep", key),
+                };
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether objects of a kind hold keys which are served as files.
+pub fn has_keys(kind: &str) -> bool {
+    kind == CONFIG_MAP_KIND || kind == SECRET_KIND
+}
+
+/// Names of the keys of a ConfigMap or Secret, sorted.
+pub fn keys(object: &Value) -> Vec<String> {
+    let mut keys: Vec<String> = ["data", "binaryData", "stringData"]
+        .iter()
+        .filter_map(|field| object[field].as_object())
+        .flat_map(|values| values.keys().cloned())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    keys
+}
+
+/// The raw value of a key. Secret data and ConfigMap binaryData are base64
+/// encoded by the API server and are decoded here, Secret values which are
+/// shown decoded or redacted are taken as they are.
+pub fn value(kind: &str, object: &Value, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    if let Some(text) = object["stringData"][key].as_str() {
+        return Ok(Some(text.as_bytes().to_vec()));
+    }
+
+    if let Some(text) = object["data"][key].as_str() {
+        return Ok(Some(if kind == SECRET_KIND && text != REDACTED {
+            base64::decode(text)?
+        } else {
+            text.as_bytes().to_vec()
+        }));
+    }
+
+    match object["binaryData"][key].as_str() {
+        Some(encoded) => Ok(Some(base64::decode(encoded)?)),
+        None => Ok(None),
+    }
+}
+
+/// A merge patch which sets a key to `value`, or removes it when `value`
+/// is `None`. ConfigMap values which are not UTF-8 go to binaryData.
+pub fn key_patch(kind: &str, object: &Value, key: &str, value: Option<&[u8]>) -> Value {
+    let mut patch = Map::new();
+
+    match value {
+        Some(value) if kind == SECRET_KIND => {
+            patch.insert(String::from("data"), json!({ key: base64::encode(value) }));
+        }
+        Some(value) => match std::str::from_utf8(value) {
+            Ok(text) => {
+                patch.insert(String::from("data"), json!({ key: text }));
+                if object["binaryData"][key].is_string() {
+                    patch.insert(String::from("binaryData"), json!({ key: null }));
+                }
+            }
+            Err(_) => {
+                patch.insert(
+                    String::from("binaryData"),
+                    json!({ key: base64::encode(value) }),
+                );
+                if object["data"][key].is_string() {
+                    patch.insert(String::from("data"), json!({ key: null }));
+                }
+            }
+        },
+        // Secrets only store data, stringData is merged into it on write
+        None if kind == SECRET_KIND => {
+            patch.insert(String::from("data"), json!({ key: null }));
+        }
+        None => {
+            for field in &["data", "binaryData"] {
+                if object[field][key].is_string() {
+                    patch.insert(field.to_string(), json!({ key: null }));
+                }
+            }
+        }
+    }
+
+    Value::Object(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_are_decoded() -> Result<(), anyhow::Error> {
+        let secret = json!({ "data": { "password": "aHVudGVyMg==" } });
+        let config_map = json!({
+            "data": { "app.conf": "debug = true\n" },
+            "binaryData": { "logo.png": "iVBORw==" }
+        });
+
+        assert_eq!(keys(&config_map), vec!["app.conf", "logo.png"]);
+        assert_eq!(
+            value(SECRET_KIND, &secret, "password")?,
+            Some(b"hunter2".to_vec())
+        );
+        assert_eq!(
+            value(CONFIG_MAP_KIND, &config_map, "app.conf")?,
+            Some(b"debug = true\n".to_vec())
+        );
+        assert_eq!(
+            value(CONFIG_MAP_KIND, &config_map, "logo.png")?,
+            Some(vec![0x89, b'P', b'N', b'G'])
+        );
+        assert_eq!(value(SECRET_KIND, &secret, "missing")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_views() -> Result<(), anyhow::Error> {
+        let stored = json!({ "data": { "password": "aHVudGVyMg==", "blob": "/w==" } });
+
+        let mut redacted = stored.clone();
+        SecretView::Redacted.apply(&mut redacted);
+        assert_eq!(redacted["data"]["password"], REDACTED);
+        assert_eq!(
+            value(SECRET_KIND, &redacted, "password")?,
+            Some(b"****".to_vec())
+        );
+
+        let mut decoded = stored.clone();
+        SecretView::Decoded.apply(&mut decoded);
+        assert_eq!(
+            decoded,
+            json!({ "data": { "blob": "/w==" }, "stringData": { "password": "hunter2" } })
+        );
+        assert_eq!(keys(&decoded), vec!["blob", "password"]);
+
+        let mut edited = json!({
+            "data": { "password": REDACTED, "token": "dG9rZW4=" },
+            "stringData": { "blob": "new" }
+        });
+        restore_redacted(&mut edited, &stored)?;
+        assert_eq!(edited["data"]["password"], "aHVudGVyMg==");
+        assert_eq!(edited["data"]["token"], "dG9rZW4=");
+
+        let mut unknown = json!({ "data": { "missing": REDACTED } });
+        assert!(restore_redacted(&mut unknown, &stored).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redacted_secret_created_by_kubectl_apply() -> Result<(), anyhow::Error> {
+        let last_applied =
+            r#"{"apiVersion":"v1","data":{"password":"aHVudGVyMg=="},"kind":"Secret"}"#;
+        let stored = json!({
+            "metadata": {
+                "name": "db-creds",
+                "annotations": { LAST_APPLIED_ANNOTATION: last_applied, "team": "db" }
+            },
+            "data": { "password": "aHVudGVyMg==" }
+        });
+
+        let mut redacted = stored.clone();
+        SecretView::Redacted.apply(&mut redacted);
+        assert_eq!(
+            redacted["metadata"]["annotations"],
+            json!({ LAST_APPLIED_ANNOTATION: REDACTED, "team": "db" })
+        );
+        assert!(!redacted.to_string().contains("aHVudGVyMg=="));
+
+        restore_redacted(&mut redacted, &stored)?;
+        assert_eq!(redacted, stored);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_patches() {
+        let config_map = json!({ "binaryData": { "blob": "AA==" } });
+
+        assert_eq!(
+            key_patch(SECRET_KIND, &json!({}), "password", Some(b"hunter2")),
+            json!({ "data": { "password": "aHVudGVyMg==" } })
+        );
+        assert_eq!(
+            key_patch(CONFIG_MAP_KIND, &config_map, "blob", Some(b"text")),
+            json!({ "data": { "blob": "text" }, "binaryData": { "blob": null } })
+        );
+        assert_eq!(
+            key_patch(CONFIG_MAP_KIND, &config_map, "blob", None),
+            json!({ "binaryData": { "blob": null } })
+        );
+    }
+}
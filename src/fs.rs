@@ -1,77 +1,588 @@
 use crate::{
-    inode::{KubeFSINodes, KubeFSInode, KubeFSLevel},
-    KubeClient,
+    inode::{KubeFSINodes, KubeFSInode, KubeFSInodeError, KubeFSLevel},
+    kube_client::KubeClientError,
+    overlay::{is_scratch_name, Overlay},
+    pod::FollowedLog,
 };
 use fuse::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
-    ReplyEntry, ReplyWrite, Request,
+    consts::FOPEN_DIRECT_IO, FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request,
+};
+use libc::{
+    c_int, EACCES, EAGAIN, EBUSY, ECONNREFUSED, EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENOENT,
+    ENOTDIR, ENOTEMPTY, EPERM, EROFS, ETIMEDOUT, O_ACCMODE, O_RDONLY, O_TRUNC,
+};
+use log::{error, info};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    sync::{Arc, Mutex, MutexGuard},
 };
-use libc::ENOENT;
-use log::{info, error};
-use std::{collections::HashMap, ffi::OsStr};
 use time::Timespec;
+use tokio::runtime::{self, Runtime};
 use users::{get_current_gid, get_current_uid};
 
+/// Upper bound on requests served at the same time.
+const WORKER_THREADS: usize = 32;
+
+/// Largest file a write or truncate can grow a buffer to. Objects are far
+/// smaller, as etcd limits them to about 1.5MB, but files in containers are
+/// buffered whole too.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The FUSE session calls in on a single thread. Every request is handed to
+/// a worker thread along with its reply, so a slow call to the API server
+/// only holds up the process which made it.
 pub struct KubeFS {
+    state: Arc<KubeFSState>,
+    workers: Runtime,
+    /// Rejects every change with `EROFS` before it reaches the cluster or
+    /// the overlay.
+    read_only: bool,
+}
+
+/// Everything a request needs. The inode table and the local files are
+/// locked separately and neither lock is held across a call to the API
+/// server. When both are needed, `files` is taken first.
+struct KubeFSState {
     inodes: KubeFSINodes,
-    swap_files: HashMap<String, SwapFile>,
+    files: Mutex<LocalFiles>,
 }
 
-const SWAP_FILE_START_INO: u64 = 1000000;
+/// Open handles and entries which only exist in the mount.
+struct LocalFiles {
+    overlay: Overlay,
+    new_objects: HashMap<u64, NewObject>,
+    handles: HashMap<u64, FileHandle>,
+    next_fh: u64,
+}
 
-struct SwapFile {
-    name: String,
+/// State for an open file. `buffer` holds a snapshot of the object taken at
+/// open, so every read through the handle sees the same document. Writes
+/// land in it too and are only sent to the API server once the complete
+/// document is known, on flush or release. Handles on the log of a running
+/// container read from `log` instead, which keeps growing while it is open.
+struct FileHandle {
     ino: u64,
+    buffer: Option<Vec<u8>>,
+    dirty: bool,
+    /// errno of a rejected commit, reported again instead of resending the
+    /// same document until the buffer is written to again
+    failed: Option<c_int>,
+    /// Opened for writing, so truncating the file by path applies to it
+    writable: bool,
+    /// resourceVersion of the object `buffer` was filled from, which edits
+    /// of it are based on
+    resource_version: Option<String>,
+    target: HandleTarget,
+    log: Option<Arc<FollowedLog>>,
+}
+
+/// What committing a handle does with the buffered document.
+#[derive(Debug, Clone, Copy)]
+enum HandleTarget {
+    Object,
+    NewObject,
+    Scratch,
+}
+
+/// A file created in a kind directory. It turns into an object once a
+/// manifest has been written to it and the file is flushed.
+struct NewObject {
+    parent: u64,
+    name: String,
 }
 
 impl KubeFS {
-    pub fn new(client: KubeClient) -> Self {
+    pub fn new(inodes: KubeFSINodes) -> Self {
+        let workers = runtime::Builder::new()
+            .threaded_scheduler()
+            .core_threads(1)
+            .max_threads(WORKER_THREADS + 1)
+            .thread_name("kubefs-worker")
+            .build()
+            .unwrap();
+
         KubeFS {
-            inodes: KubeFSINodes::new(Box::new(client)),
-            swap_files: HashMap::new(),
+            state: Arc::new(KubeFSState {
+                inodes,
+                files: Mutex::new(LocalFiles {
+                    overlay: Overlay::new(),
+                    new_objects: HashMap::new(),
+                    handles: HashMap::new(),
+                    next_fh: 1,
+                }),
+            }),
+            workers,
+            read_only: false,
         }
     }
 
-    pub fn create_empty_swap_file(&mut self, name: &str) {
-        let name = String::from(name);
-        self.swap_files.insert(
-            name.clone(),
-            SwapFile {
-                name: name.clone(),
-                ino: SWAP_FILE_START_INO + (self.swap_files.len() as u64),
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Runs a request on a worker thread. Requests are not cancelled when
+    /// their caller is interrupted: fuse 0.3 answers FUSE_INTERRUPT with
+    /// ENOSYS without passing it on, after which the kernel stops sending
+    /// interrupts. Only the request timeout of the client bounds how long a
+    /// call to the API server runs.
+    fn dispatch(&self, f: impl FnOnce(&KubeFSState) + Send + 'static) {
+        let state = self.state.clone();
+        self.workers.handle().spawn_blocking(move || f(&state));
+    }
+}
+
+impl KubeFSState {
+    fn files(&self) -> MutexGuard<'_, LocalFiles> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_handle(&self, ino: u64, target: HandleTarget, writable: bool) -> u64 {
+        let mut files = self.files();
+        let fh = files.next_fh;
+        files.next_fh += 1;
+
+        files.handles.insert(
+            fh,
+            FileHandle {
+                ino,
+                buffer: match target {
+                    HandleTarget::NewObject => Some(vec![]),
+                    _ => None,
+                },
+                dirty: false,
+                failed: None,
+                writable,
+                resource_version: None,
+                target,
+                log: None,
             },
         );
+
+        fh
     }
 
-    pub fn create_swap_file_attr(&self, name: &str) -> FileAttr {
-        let name = name.to_string();
-        let swap_file = self.swap_files.get(&name).unwrap();
+    /// Size and modification time of the log followed for `ino` by the
+    /// handle which has seen the most of it, so `tail -f` notices growth.
+    fn followed_log_stat(&self, ino: u64) -> Option<(u64, i64)> {
+        self.files()
+            .handles
+            .values()
+            .filter(|handle| handle.ino == ino)
+            .filter_map(|handle| handle.log.as_ref())
+            .map(|log| log.stat())
+            .max()
+    }
 
-        let inode = KubeFSInode {
-            ino: swap_file.ino,
-            parent: None,
-            name: swap_file.name.clone(),
-            level: KubeFSLevel::File,
+    /// Runs `f` on the buffer of a handle, seeded with the current contents
+    /// of the object the first time it is needed. Scratch files are written
+    /// in place.
+    fn with_buffer<R>(&self, fh: u64, f: impl FnOnce(&mut Vec<u8>) -> R) -> anyhow::Result<R> {
+        let ino = {
+            let mut files = self.files();
+            let files = &mut *files;
+            let handle = files
+                .handles
+                .get_mut(&fh)
+                .ok_or_else(|| anyhow::anyhow!("Unknown file handle {}", fh))?;
+
+            match (handle.target, handle.buffer.as_mut()) {
+                (HandleTarget::Scratch, _) => {
+                    let ino = handle.ino;
+                    return files
+                        .overlay
+                        .get_mut(&ino)
+                        .map(|file| f(&mut file.data))
+                        .ok_or_else(|| KubeFSInodeError::MissingInode.into());
+                }
+                (_, Some(buffer)) => return Ok(f(buffer)),
+                (_, None) => handle.ino,
+            }
+        };
+
+        let (contents, resource_version) = self.inodes.read_file(&ino)?;
+
+        let mut files = self.files();
+        let handle = files
+            .handles
+            .get_mut(&fh)
+            .ok_or_else(|| anyhow::anyhow!("Unknown file handle {}", fh))?;
+
+        if handle.buffer.is_none() {
+            handle.resource_version = resource_version;
+        }
+
+        Ok(f(handle.buffer.get_or_insert(contents)))
+    }
+
+    /// Attaches a followed log to a handle on a log file. Logs of previous
+    /// instances are complete and read like any other file.
+    fn follow_handle(&self, fh: u64, ino: u64) -> anyhow::Result<()> {
+        let log = Arc::new(FollowedLog::new());
+        if !self.inodes.follow_log(&ino, &log)? {
+            return self.with_buffer(fh, |_| ());
+        }
+
+        if let Some(handle) = self.files().handles.get_mut(&fh) {
+            handle.log = Some(log);
+        }
+
+        Ok(())
+    }
+
+    fn set_dirty(&self, fh: u64) {
+        if let Some(handle) = self.files().handles.get_mut(&fh) {
+            handle.dirty = true;
+            handle.failed = None;
+        }
+    }
+
+    fn truncate_handle(&self, fh: u64, size: u64) -> anyhow::Result<()> {
+        if size == 0 {
+            if let Some(handle) = self.files().handles.get_mut(&fh) {
+                if let HandleTarget::Object = handle.target {
+                    handle.buffer = Some(vec![]);
+                }
+            }
+        }
+
+        self.with_buffer(fh, |buffer| buffer.resize(size as usize, 0))?;
+        self.set_dirty(fh);
+
+        Ok(())
+    }
+
+    /// Truncates a file which nobody has open for writing by writing the
+    /// truncated contents back right away.
+    fn truncate_file(&self, ino: u64, size: u64) -> anyhow::Result<()> {
+        let level = self.inodes.get_inode(&ino).map(|inode| inode.level);
+        if level.is_some_and(is_read_only) {
+            return Err(std::io::Error::from_raw_os_error(EACCES).into());
+        }
+
+        let (mut data, resource_version) = self.inodes.read_file(&ino)?;
+        if data.len() as u64 != size {
+            data.resize(size as usize, 0);
+            self.inodes
+                .write_file(&ino, data, resource_version.as_deref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the buffered document of a handle to the API server if it has
+    /// been written to since the last commit. Failures are recorded in the
+    /// error log before being returned.
+    fn commit_handle(&self, fh: u64) -> anyhow::Result<()> {
+        let failed = self
+            .files()
+            .handles
+            .get(&fh)
+            .and_then(|handle| handle.failed);
+        if let Some(code) = failed {
+            return Err(std::io::Error::from_raw_os_error(code).into());
+        }
+
+        let res = self.try_commit_handle(fh);
+
+        if let Err(e) = &res {
+            let ino = self.files().handles.get(&fh).map(|handle| handle.ino);
+            if let Some(ino) = ino {
+                error!("Error updating ino {}: {:#}", ino, e);
+                self.inodes.record_error(&ino, e);
+            }
+        }
+
+        res
+    }
+
+    fn try_commit_handle(&self, fh: u64) -> anyhow::Result<()> {
+        let (ino, target, data, resource_version, new_object) = {
+            let files = self.files();
+
+            match files.handles.get(&fh) {
+                Some(handle) if handle.dirty => (
+                    handle.ino,
+                    handle.target,
+                    handle.buffer.clone().unwrap_or_default(),
+                    handle.resource_version.clone(),
+                    files
+                        .new_objects
+                        .get(&handle.ino)
+                        .map(|o| (o.parent, o.name.clone())),
+                ),
+                _ => return Ok(()),
+            }
         };
 
-        self.create_file_attr(&inode)
+        let res = self.commit(
+            fh,
+            ino,
+            target,
+            &data,
+            resource_version.as_deref(),
+            new_object,
+        );
+
+        // A rejected document is not sent again, its error is reported by
+        // the flush and release which follow instead. Writes which came in
+        // while committing are left for the next commit.
+        if let Some(handle) = self.files().handles.get_mut(&fh) {
+            if handle.buffer.as_deref().unwrap_or_default() == data.as_slice() {
+                handle.dirty = false;
+                handle.failed = res.as_ref().err().map(errno);
+            }
+        }
+
+        res
+    }
+
+    /// Sends a snapshot of the buffered document of a handle on to its
+    /// target. Objects are updated based on the `resource_version` the
+    /// handle last read or wrote.
+    fn commit(
+        &self,
+        fh: u64,
+        ino: u64,
+        target: HandleTarget,
+        data: &[u8],
+        resource_version: Option<&str>,
+        new_object: Option<(u64, String)>,
+    ) -> anyhow::Result<()> {
+        match target {
+            HandleTarget::Object => {
+                let written = self
+                    .inodes
+                    .write_file(&ino, data.to_vec(), resource_version)?;
+
+                // Later commits of the handle build on its own update
+                if let Some(handle) = self.files().handles.get_mut(&fh) {
+                    handle.resource_version = written;
+                }
+
+                info!("update completed for ino {}", ino);
+            }
+            HandleTarget::NewObject => {
+                // Editors probe directories by creating and closing empty files
+                if data.iter().all(u8::is_ascii_whitespace) {
+                    return Ok(());
+                }
+
+                let (parent, name) = new_object.ok_or(KubeFSInodeError::MissingInode)?;
+
+                let inode = self.inodes.create_object(&name, &parent, data)?;
+
+                info!("created {} from ino {}", inode.name, ino);
+
+                let mut files = self.files();
+                files.new_objects.remove(&ino);
+                self.inodes.release_ino(&ino);
+
+                // Writes after the first commit update the created object
+                if let Some(handle) = files.handles.get_mut(&fh) {
+                    handle.ino = inode.ino;
+                    handle.target = HandleTarget::Object;
+                }
+            }
+            HandleTarget::Scratch => {}
+        }
+
+        Ok(())
+    }
+
+    /// Attributes for entries which only exist locally, scratch files and
+    /// objects which have not been created yet.
+    fn local_file_attr(&self, ino: u64) -> Option<FileAttr> {
+        let (parent, name, size) = {
+            let files = self.files();
+
+            match files.overlay.get(&ino) {
+                Some(file) => (file.parent, file.name.clone(), Some(file.data.len())),
+                None => {
+                    let new_object = files.new_objects.get(&ino)?;
+                    (new_object.parent, new_object.name.clone(), None)
+                }
+            }
+        };
+
+        let mut attr = self.create_file_attr(&KubeFSInode {
+            ino,
+            parent: Some(parent),
+            name,
+            level: KubeFSLevel::File,
+        });
+        if let Some(size) = size {
+            attr.size = size as u64;
+        }
+
+        Some(attr)
+    }
+
+    /// Finds a local entry by path, either a scratch file or an object which
+    /// has not been created yet.
+    fn find_local_file(&self, parent: &u64, name: &str) -> Option<u64> {
+        let files = self.files();
+
+        files.overlay.find(parent, name).or_else(|| {
+            files
+                .new_objects
+                .iter()
+                .find(|(_, o)| o.parent == *parent && o.name == name)
+                .map(|(ino, _)| *ino)
+        })
+    }
+
+    fn remove_scratch_file(&self, files: &mut LocalFiles, ino: &u64) {
+        if files.overlay.remove(ino).is_some() {
+            self.inodes.release_ino(ino);
+        }
+    }
+
+    fn create_scratch_file(&self, parent: &u64, name: &str, data: Vec<u8>) -> u64 {
+        let mut files = self.files();
+
+        if let Some(existing) = files.overlay.find(parent, name) {
+            self.remove_scratch_file(&mut files, &existing);
+        }
+
+        let ino = self.inodes.allocate_ino(parent, name);
+        files.overlay.insert(ino, *parent, name);
+        if let Some(file) = files.overlay.get_mut(&ino) {
+            file.data = data;
+        }
+
+        ino
+    }
+
+    /// Renaming a scratch file over an object file updates the object with
+    /// its contents, which is how editors with atomic saves and `sed -i`
+    /// replace a file. Renaming it to a new manifest name in a kind directory
+    /// creates the object. Anything else moves the file within the overlay.
+    fn rename_scratch_file(&self, ino: u64, newparent: u64, newname: &str) -> anyhow::Result<()> {
+        let target = self
+            .inodes
+            .lookup_inode_by_parent_and_name(&newparent, newname);
+        let data = self
+            .files()
+            .overlay
+            .get(&ino)
+            .map(|file| file.data.clone())
+            .unwrap_or_default();
+
+        match target {
+            Some(target)
+                if matches!(
+                    target.level,
+                    KubeFSLevel::File
+                        | KubeFSLevel::Manifest
+                        | KubeFSLevel::ContainerFsFile
+                        | KubeFSLevel::DataKey
+                ) =>
+            {
+                self.inodes.write_file(&target.ino, data, None)?;
+                self.remove_scratch_file(&mut self.files(), &ino);
+            }
+            Some(_) => return Err(std::io::Error::from_raw_os_error(EISDIR).into()),
+            None if !is_scratch_name(newname)
+                && matches!(
+                    self.inodes.get_inode(&newparent).map(|inode| inode.level),
+                    Some(KubeFSLevel::Object)
+                ) =>
+            {
+                self.inodes.create_object(newname, &newparent, &data)?;
+                self.remove_scratch_file(&mut self.files(), &ino);
+            }
+            None => {
+                let mut files = self.files();
+                if let Some(existing) = files.overlay.find(&newparent, newname) {
+                    self.remove_scratch_file(&mut files, &existing);
+                }
+                files.overlay.rename(&ino, &newparent, newname);
+                self.inodes.rename_ino(&ino, &newparent, newname);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rename_entry(
+        &self,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+        newname: &str,
+    ) -> anyhow::Result<()> {
+        let scratch_file = self.files().overlay.find(&parent, name);
+        if let Some(ino) = scratch_file {
+            return self.rename_scratch_file(ino, newparent, newname);
+        }
+
+        let inode = self
+            .inodes
+            .lookup_inode_by_parent_and_name(&parent, name)
+            .ok_or(KubeFSInodeError::MissingInode)?;
+
+        match inode.level {
+            // Editors move the original aside as a backup before saving. The
+            // object stays where it is and the backup becomes a scratch copy.
+            KubeFSLevel::File
+            | KubeFSLevel::Manifest
+            | KubeFSLevel::ContainerFsFile
+            | KubeFSLevel::DataKey
+                if is_scratch_name(newname) =>
+            {
+                let data = self.inodes.get_file_bytes(&inode.ino)?;
+                self.create_scratch_file(&newparent, newname, data);
+
+                Ok(())
+            }
+            _ => Err(std::io::Error::from_raw_os_error(EPERM).into()),
+        }
+    }
+
+    fn create_new_object(&self, parent: &u64, name: &str) -> u64 {
+        let mut files = self.files();
+        let ino = self.inodes.allocate_ino(parent, name);
+        files.new_objects.insert(
+            ino,
+            NewObject {
+                parent: *parent,
+                name: name.to_string(),
+            },
+        );
+
+        ino
     }
 
     fn create_file_attr(&self, inode: &KubeFSInode) -> FileAttr {
+        let stat = self.inodes.get_stat(&inode.ino).unwrap_or_default();
+        let created = stat
+            .created
+            .map(|sec| Timespec::new(sec, 0))
+            .unwrap_or(CREATE_TIME);
+        let modified = stat
+            .modified
+            .map(|sec| Timespec::new(sec, 0))
+            .unwrap_or(created);
+
         FileAttr {
             ino: inode.ino,
-            size: 10000,
-            blocks: 0,
-            atime: CREATE_TIME,
-            mtime: CREATE_TIME,
-            ctime: CREATE_TIME,
-            crtime: CREATE_TIME,
-            kind: match inode.level {
-                KubeFSLevel::File => FileType::RegularFile,
-                _ => FileType::Directory,
-            },
+            size: stat.size,
+            blocks: stat.size.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: created,
+            crtime: created,
+            kind: file_type(inode.level),
             perm: match inode.level {
-                KubeFSLevel::File => 0o644,
+                KubeFSLevel::File
+                | KubeFSLevel::Manifest
+                | KubeFSLevel::ContainerFsFile
+                | KubeFSLevel::DataKey => 0o644,
+                KubeFSLevel::Exec => 0o200,
+                level if is_read_only(level) => 0o444,
                 _ => 0o755,
             },
             nlink: 2,
@@ -83,10 +594,105 @@ impl KubeFS {
     }
 }
 
-#[derive(Debug, Clone)]
-struct FSNamespace {
-    name: String,
-    attrs: FileAttr,
+fn file_type(level: KubeFSLevel) -> FileType {
+    match level {
+        KubeFSLevel::File
+        | KubeFSLevel::Manifest
+        | KubeFSLevel::Log
+        | KubeFSLevel::ContainerFile
+        | KubeFSLevel::Exec
+        | KubeFSLevel::ExecOutput
+        | KubeFSLevel::ContainerFsFile
+        | KubeFSLevel::DataKey
+        | KubeFSLevel::ErrorLog => FileType::RegularFile,
+        _ => FileType::Directory,
+    }
+}
+
+/// Files which are served but cannot be written to.
+fn is_read_only(level: KubeFSLevel) -> bool {
+    matches!(
+        level,
+        KubeFSLevel::Log
+            | KubeFSLevel::ContainerFile
+            | KubeFSLevel::ExecOutput
+            | KubeFSLevel::ErrorLog
+    )
+}
+
+/// Recovers the errno behind a failed command in a container from the
+/// `strerror` text coreutils and busybox end their messages with.
+fn container_errno(message: &str) -> c_int {
+    const MESSAGES: [(&str, c_int); 7] = [
+        ("No such file or directory", ENOENT),
+        ("Permission denied", EACCES),
+        ("Read-only file system", EROFS),
+        ("File exists", EEXIST),
+        ("Not a directory", ENOTDIR),
+        ("Is a directory", EISDIR),
+        ("Directory not empty", ENOTEMPTY),
+    ];
+
+    MESSAGES
+        .iter()
+        .find(|(text, _)| message.contains(text))
+        .map(|(_, code)| *code)
+        .unwrap_or(EIO)
+}
+
+/// The part of `data` covered by a read, empty past the end.
+fn slice(data: &[u8], offset: i64, size: u32) -> &[u8] {
+    let start = (offset.max(0) as usize).min(data.len());
+    let end = start.saturating_add(size as usize).min(data.len());
+
+    &data[start..end]
+}
+
+/// Picks the errno reported to the process for a failed operation so that
+/// callers can tell "not found" apart from "not allowed" or "try again".
+fn errno(e: &anyhow::Error) -> c_int {
+    if let Some(e) = e.downcast_ref::<KubeClientError>() {
+        return match e {
+            KubeClientError::Api { code, reason, .. } => match (code, reason.as_str()) {
+                (409, "AlreadyExists") => EEXIST,
+                (409, _) => EBUSY,
+                (400, _) | (422, _) => EINVAL,
+                (401, _) | (403, _) => EACCES,
+                (404, _) | (410, _) => ENOENT,
+                (405, _) => EPERM,
+                (429, _) => EAGAIN,
+                (504, _) => ETIMEDOUT,
+                _ => EIO,
+            },
+            KubeClientError::Timeout(_) => ETIMEDOUT,
+            KubeClientError::Connection(_) => ECONNREFUSED,
+            KubeClientError::Decode(_) => EIO,
+            KubeClientError::Request(_) => EINVAL,
+            KubeClientError::UnknownKind(_) => ENOENT,
+            KubeClientError::InvalidManifest(_) => EINVAL,
+            KubeClientError::Exec(_) => EIO,
+        };
+    }
+
+    if let Some(code) = e
+        .downcast_ref::<std::io::Error>()
+        .and_then(|e| e.raw_os_error())
+    {
+        code
+    } else if let Some(KubeFSInodeError::ExcludedNamespace(_)) = e.downcast_ref() {
+        EACCES
+    } else if let Some(KubeFSInodeError::ContainerCommand(message)) = e.downcast_ref() {
+        container_errno(message)
+    } else if e.is::<KubeFSInodeError>() {
+        ENOENT
+    } else if e.is::<serde_yaml::Error>()
+        || e.is::<serde_json::Error>()
+        || e.is::<std::string::FromUtf8Error>()
+    {
+        EINVAL
+    } else {
+        EIO
+    }
 }
 
 const TTL: Timespec = Timespec { sec: 1, nsec: 0 }; // 1 second
@@ -96,34 +702,33 @@ const CREATE_TIME: Timespec = Timespec {
     nsec: 0,
 };
 
-impl Filesystem for KubeFS {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+/// Request handlers, run on the worker threads.
+impl KubeFSState {
+    fn lookup(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
         info!(
             "Lookup called with parent = {} and name = {:?}",
             parent, name
         );
 
         if let Some(name) = name.to_str() {
-            // If swap file then return
-            if name.contains("swp") {
-                if self.swap_files.get(name).is_some() {
-                    reply.entry(&TTL, &self.create_swap_file_attr(&name), 0);
-                    return;
-                }
+            let local_file = self.find_local_file(&parent, name);
+            if let Some(attr) = local_file.and_then(|ino| self.local_file_attr(ino)) {
+                reply.entry(&TTL, &attr, 0);
+                return;
             }
 
             let mut inode = self.inodes.lookup_inode_by_parent_and_name(&parent, name);
 
             if inode.is_none() {
-                let res = self.inodes.fetch_child_nodes_for_node(&parent);
-                if res.is_err() {
-                    reply.error(ENOENT);
+                if let Err(e) = self.inodes.fetch_child_nodes_for_node(&parent) {
+                    reply.error(errno(&e));
                     return;
                 }
                 inode = self.inodes.lookup_inode_by_parent_and_name(&parent, name);
             }
 
             if let Some(inode) = inode {
+                self.inodes.increment_lookup(&inode.ino);
                 reply.entry(&TTL, &self.create_file_attr(&inode), 0)
             } else {
                 reply.error(ENOENT)
@@ -133,129 +738,521 @@ impl Filesystem for KubeFS {
         }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+    fn getattr(&self, ino: u64, reply: ReplyAttr) {
         info!("getattr called with ino = {}", ino);
 
-        let inode = self.inodes.get_inode(&ino);
+        match self.inodes.get_inode(&ino) {
+            Some(inode) => {
+                let mut attr = self.create_file_attr(&inode);
+                if let Some((size, modified)) = self.followed_log_stat(ino) {
+                    attr.size = size;
+                    attr.blocks = size.div_ceil(512);
+                    attr.mtime = Timespec::new(modified, 0);
+                }
 
-        match inode {
-            Some(inode) => reply.attr(&TTL, &self.create_file_attr(&inode)),
-            None => reply.error(ENOENT),
+                reply.attr(&TTL, &attr)
+            }
+            None => match self.local_file_attr(ino) {
+                Some(attr) => reply.attr(&TTL, &attr),
+                None => reply.error(ENOENT),
+            },
         }
     }
 
-    fn read(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        _size: u32,
-        reply: ReplyData,
-    ) {
-        info!("read called with ino = {}", ino);
+    fn read(&self, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        info!(
+            "read called with ino = {}, fh = {}, offset = {}, size = {}",
+            ino, fh, offset, size
+        );
 
-        let data = self.inodes.get_file_contents(&ino);
+        let log = self
+            .files()
+            .handles
+            .get(&fh)
+            .and_then(|handle| handle.log.clone());
+        let res = match log {
+            Some(log) => {
+                if let Some(e) = log.take_error() {
+                    self.inodes.record_error(&ino, &e);
+                }
+                Ok(log.read(|data| slice(data, offset, size).to_vec()))
+            }
+            None => self.with_buffer(fh, |data| slice(data, offset, size).to_vec()),
+        };
 
-        match data {
-            Ok(data) => reply.data(&data.as_bytes()[offset as usize..]),
-            Err(_) => reply.error(ENOENT),
+        match res {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(errno(&e)),
         };
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
+    fn readdir(&self, ino: u64, offset: i64, mut reply: ReplyDirectory) {
         info!("readdir called with ino = {}", ino);
         let res = self.inodes.fetch_child_nodes_for_node(&ino);
 
         match res {
             Ok(_) => {
-                let child_inodes = self.inodes.find_inode_by_parent(&ino);
-                for (i, inode) in child_inodes.iter().enumerate().skip(offset as usize) {
-                    reply.add(
-                        inode.ino,
-                        (i + 1) as i64,
-                        match inode.level {
-                            KubeFSLevel::File => FileType::RegularFile,
-                            _ => FileType::Directory,
-                        },
-                        &inode.name,
-                    );
+                let mut entries: Vec<(u64, FileType, String)> = self
+                    .inodes
+                    .find_inode_by_parent(&ino)
+                    .iter()
+                    .map(|inode| (inode.ino, file_type(inode.level), inode.name.clone()))
+                    .collect();
+                entries.extend(
+                    self.files()
+                        .overlay
+                        .children(&ino)
+                        .into_iter()
+                        .map(|(ino, file)| (ino, FileType::RegularFile, file.name.clone())),
+                );
+
+                for (i, (ino, kind, name)) in entries.iter().enumerate().skip(offset as usize) {
+                    reply.add(*ino, (i + 1) as i64, *kind, name);
                 }
                 reply.ok();
             }
-            Err(_) => reply.error(ENOENT),
+            Err(e) => reply.error(errno(&e)),
         };
     }
 
-    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
+    fn mkdir(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
         if let Some(name) = name.to_str() {
             let res = self.inodes.create_object(name, &parent, &[]);
 
             match res {
-                Ok(()) => {
-                    let inode = KubeFSInode {
-                        ino: 10000000,
-                        level: KubeFSLevel::Namespace,
-                        name: name.to_string(),
-                        parent: Some(parent),
-                    };
-
+                Ok(inode) => {
+                    self.inodes.increment_lookup(&inode.ino);
                     reply.entry(&TTL, &self.create_file_attr(&inode), 0);
                 }
-                Err(_) => {
-                    reply.error(ENOENT);
+                Err(e) => {
+                    self.inodes.record_error(&parent, &e);
+                    reply.error(errno(&e));
                 }
             };
         }
     }
 
-    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    fn rmdir(&self, parent: u64, name: &OsStr, reply: ReplyEmpty) {
         if let Some(name) = name.to_str() {
             let res = self.inodes.delete_object(name, &parent);
 
             match res {
                 Ok(()) => reply.ok(),
-                Err(_) => reply.error(ENOENT),
+                Err(e) => {
+                    self.inodes.record_error(&parent, &e);
+                    reply.error(errno(&e))
+                }
+            };
+        }
+    }
+
+    fn open(&self, ino: u64, flags: u32, reply: ReplyOpen) {
+        info!("open called with ino = {}, flags = {}", ino, flags);
+
+        let level = self.inodes.get_inode(&ino).map(|inode| inode.level);
+        if level.is_some_and(is_read_only) && flags as c_int & O_ACCMODE != O_RDONLY {
+            reply.error(EACCES);
+            return;
+        }
+
+        let target = {
+            let files = self.files();
+            if files.new_objects.contains_key(&ino) {
+                HandleTarget::NewObject
+            } else if files.overlay.contains(&ino) {
+                HandleTarget::Scratch
+            } else {
+                HandleTarget::Object
+            }
+        };
+
+        let fh = self.open_handle(ino, target, flags as c_int & O_ACCMODE != O_RDONLY);
+
+        let res = match (target, level) {
+            (HandleTarget::Object, Some(KubeFSLevel::Log)) => self.follow_handle(fh, ino),
+            (HandleTarget::Object, _) => self.with_buffer(fh, |_| ()),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = res {
+            error!("Error opening ino {}: {}", ino, e);
+            self.inodes.record_error(&ino, &e);
+            self.files().handles.remove(&fh);
+            reply.error(errno(&e));
+            return;
+        }
+
+        // The snapshot can be larger than the size the kernel last saw, so
+        // reads must not be cut off at the cached size
+        reply.opened(fh, FOPEN_DIRECT_IO);
+    }
+
+    fn setattr(&self, ino: u64, size: Option<u64>, fh: Option<u64>, reply: ReplyAttr) {
+        info!("setattr called with ino = {}, size = {:?}", ino, size);
+
+        if let Some(size) = size {
+            if size > MAX_FILE_SIZE {
+                reply.error(EFBIG);
+                return;
+            }
+
+            let fhs: Option<Vec<u64>> = {
+                let mut files = self.files();
+
+                if let Some(file) = files.overlay.get_mut(&ino) {
+                    // Scratch files are shared by all of their handles
+                    file.data.resize(size as usize, 0);
+                    Some(vec![])
+                } else {
+                    // Truncating by path applies to the handles open for
+                    // writing, or to the file itself when there are none
+                    match fh {
+                        Some(fh) => Some(vec![fh]),
+                        None => {
+                            let fhs: Vec<u64> = files
+                                .handles
+                                .iter()
+                                .filter(|(_, handle)| handle.ino == ino && handle.writable)
+                                .map(|(fh, _)| *fh)
+                                .collect();
+
+                            Some(fhs).filter(|fhs| !fhs.is_empty())
+                        }
+                    }
+                }
+            };
+
+            let res = match fhs {
+                Some(fhs) => fhs
+                    .into_iter()
+                    .try_for_each(|fh| self.truncate_handle(fh, size)),
+                None => self.truncate_file(ino, size),
+            };
+            if let Err(e) = res {
+                error!("Error truncating ino {}: {}", ino, e);
+                self.inodes.record_error(&ino, &e);
+                reply.error(errno(&e));
+                return;
+            }
+        }
+
+        self.getattr(ino, reply);
+    }
+
+    fn write(&self, ino: u64, fh: u64, offset: i64, data: &[u8], reply: ReplyWrite) {
+        info!(
+            "Write called with ino = {}, offset = {}, len = {}, fh = {}",
+            ino,
+            offset,
+            data.len(),
+            fh
+        );
+
+        if offset < 0 || offset as u64 + data.len() as u64 > MAX_FILE_SIZE {
+            reply.error(EFBIG);
+            return;
+        }
+
+        let res = self.with_buffer(fh, |buffer| {
+            let offset = offset as usize;
+            if buffer.len() < offset + data.len() {
+                buffer.resize(offset + data.len(), 0);
+            }
+            buffer[offset..offset + data.len()].copy_from_slice(data);
+        });
+
+        if let Err(e) = res {
+            error!("Error loading ino {} for write: {}", ino, e);
+            reply.error(errno(&e));
+            return;
+        }
+
+        self.set_dirty(fh);
+
+        reply.written(data.len() as u32);
+    }
+
+    fn flush(&self, ino: u64, fh: u64, reply: ReplyEmpty) {
+        info!("flush called with ino = {}, fh = {}", ino, fh);
+
+        match self.commit_handle(fh) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn release(&self, ino: u64, fh: u64, reply: ReplyEmpty) {
+        info!("release called with ino = {}, fh = {}", ino, fh);
+
+        // The kernel ignores errors from release, close(2) sees the one from
+        // flush instead
+        let res = self.commit_handle(fh);
+
+        let mut files = self.files();
+        if let Some(handle) = files.handles.remove(&fh) {
+            if let Some(log) = &handle.log {
+                log.close();
+                if let Some(e) = log.take_error() {
+                    self.inodes.record_error(&handle.ino, &e);
+                }
+            }
+
+            if let HandleTarget::NewObject = handle.target {
+                // Never written to, so the file goes away with its handle
+                files.new_objects.remove(&handle.ino);
+                self.inodes.release_ino(&handle.ino);
+            }
+        }
+        drop(files);
+
+        match res {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn create(&self, parent: u64, name: &OsStr, reply: ReplyCreate) {
+        info!("Create called with parent = {}, name = {:?}", parent, name);
+
+        if let Some(name) = name.to_str() {
+            // Files created in a kind directory become new objects, ones
+            // created in a container or as a key of an object are created
+            // there, anything else only lives in the overlay
+            let (ino, target) = match self.inodes.get_inode(&parent).map(|inode| inode.level) {
+                Some(KubeFSLevel::ContainerFsRoot)
+                | Some(KubeFSLevel::ContainerFsDir)
+                | Some(KubeFSLevel::ObjectDirectory)
+                    if !is_scratch_name(name) =>
+                {
+                    self.create_remote_file(parent, name, reply);
+                    return;
+                }
+                Some(KubeFSLevel::Object) if !is_scratch_name(name) => (
+                    self.create_new_object(&parent, name),
+                    HandleTarget::NewObject,
+                ),
+                Some(_) => (
+                    self.create_scratch_file(&parent, name, vec![]),
+                    HandleTarget::Scratch,
+                ),
+                None => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+
+            let fh = self.open_handle(ino, target, true);
+            match self.local_file_attr(ino) {
+                Some(attr) => reply.created(&TTL, &attr, 0, fh, 0),
+                None => reply.error(ENOENT),
+            }
+        } else {
+            reply.error(ENOENT);
+        }
+    }
+
+    /// Files created in a container or as a key are created there right
+    /// away, and are then written like any existing file.
+    fn create_remote_file(&self, parent: u64, name: &str, reply: ReplyCreate) {
+        match self.inodes.create_file(name, &parent) {
+            Ok(inode) => {
+                self.inodes.increment_lookup(&inode.ino);
+                let fh = self.open_handle(inode.ino, HandleTarget::Object, true);
+                if let Some(handle) = self.files().handles.get_mut(&fh) {
+                    handle.buffer = Some(vec![]);
+                }
+
+                reply.created(&TTL, &self.create_file_attr(&inode), 0, fh, FOPEN_DIRECT_IO);
+            }
+            Err(e) => {
+                self.inodes.record_error(&parent, &e);
+                reply.error(errno(&e));
+            }
+        }
+    }
+
+    fn unlink(&self, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        info!("Unlink called with parent = {}, name = {:?}", parent, name);
+        if let Some(name) = name.to_str() {
+            {
+                let mut files = self.files();
+                if let Some(ino) = files.overlay.find(&parent, name) {
+                    self.remove_scratch_file(&mut files, &ino);
+                    drop(files);
+                    reply.ok();
+                    return;
+                }
+            }
+
+            match self.inodes.delete_object(name, &parent) {
+                Ok(()) => reply.ok(),
+                Err(e) => {
+                    error!("Error deleting {}: {}", name, e);
+                    self.inodes.record_error(&parent, &e);
+                    reply.error(errno(&e))
+                }
             };
+        } else {
+            reply.error(ENOENT);
         }
     }
 
+    fn rename(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEmpty,
+    ) {
+        info!(
+            "rename called with parent = {}, name = {:?}, newparent = {}, newname = {:?}",
+            parent, name, newparent, newname
+        );
+
+        let (name, newname) = match (name.to_str(), newname.to_str()) {
+            (Some(name), Some(newname)) => (name, newname),
+            _ => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match self.rename_entry(parent, name, newparent, newname) {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                error!("Error renaming {} to {}: {:#}", name, newname, e);
+                self.inodes.record_error(&newparent, &e);
+                reply.error(errno(&e))
+            }
+        }
+    }
+}
+
+impl Filesystem for KubeFS {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.lookup(parent, &name, reply));
+    }
+
+    fn forget(&mut self, _req: &Request, ino: u64, nlookup: u64) {
+        info!("forget called with ino = {}, nlookup = {}", ino, nlookup);
+
+        // No reply to wait for, and only the inode table is touched
+        self.state.inodes.forget(&ino, nlookup);
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        self.dispatch(move |fs| fs.getattr(ino, reply));
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        reply: ReplyData,
+    ) {
+        self.dispatch(move |fs| fs.read(ino, fh, offset, size, reply));
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, reply: ReplyDirectory) {
+        self.dispatch(move |fs| fs.readdir(ino, offset, reply));
+    }
+
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.mkdir(parent, &name, reply));
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.rmdir(parent, &name, reply));
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: u32, reply: ReplyOpen) {
+        let flags = flags as c_int;
+        if self.read_only && (flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0) {
+            reply.error(EROFS);
+            return;
+        }
+
+        self.dispatch(move |fs| fs.open(ino, flags as u32, reply));
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<Timespec>,
+        _mtime: Option<Timespec>,
+        fh: Option<u64>,
+        _crtime: Option<Timespec>,
+        _chgtime: Option<Timespec>,
+        _bkuptime: Option<Timespec>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        self.dispatch(move |fs| fs.setattr(ino, size, fh, reply));
+    }
+
     fn write(
         &mut self,
         _req: &Request,
         ino: u64,
         fh: u64,
-        _offset: i64,
+        offset: i64,
         data: &[u8],
         _flags: u32,
         reply: ReplyWrite,
     ) {
-        info!(
-            "Write called with ino = {}, data = {:?}, fh = {}",
-            ino,
-            data.to_ascii_lowercase(),
-            fh
-        );
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        let data = data.to_vec();
+        self.dispatch(move |fs| fs.write(ino, fh, offset, &data, reply));
+    }
 
-        let d = std::str::from_utf8(data);
+    fn flush(&mut self, _req: &Request, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        self.dispatch(move |fs| fs.flush(ino, fh, reply));
+    }
 
-        if let Ok(data) = d {
-            // Find ino in nodes
-            // Write to K8s
-            match self.inodes.update_object(&ino, data) {
-                Ok(_) => info!("write - update completed for ino {}", ino),
-                Err(e) => error!("Error updating ino {}", e)
-            };
-        }
+    fn fsync(&mut self, _req: &Request, ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        info!("fsync called with ino = {}, fh = {}", ino, fh);
 
-        reply.written(data.len() as u32);
+        self.dispatch(move |fs| fs.flush(ino, fh, reply));
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.dispatch(move |fs| fs.release(ino, fh, reply));
     }
 
     fn create(
@@ -267,30 +1264,97 @@ impl Filesystem for KubeFS {
         _flags: u32,
         reply: ReplyCreate,
     ) {
-        info!("Create called with parent = {}, name = {:?}", parent, name);
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.create(parent, &name, reply));
+    }
 
-        if let Some(name) = name.to_str() {
-            // If swap then add to swap files
-            if name.contains("swp") {
-                self.create_empty_swap_file(name);
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.error(EROFS);
+            return;
+        }
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.unlink(parent, &name, reply));
+    }
 
-                reply.created(&TTL, &self.create_swap_file_attr(&name), 0, 1, 0o644);
-            }
-        } else {
-            reply.error(ENOENT);
+    fn rename(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEmpty,
+    ) {
+        if self.read_only {
+            reply.error(EROFS);
+            return;
         }
+        let (name, newname) = (name.to_os_string(), newname.to_os_string());
+        self.dispatch(move |fs| fs.rename(parent, &name, newparent, &newname, reply));
     }
+}
 
-    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        info!("Unlink called with parent = {}, name = {:?}", parent, name);
-        if let Some(name) = name.to_str() {
-            // If swap then remove swap file
-            if name.contains("swp") {
-                self.swap_files.remove(name);
-                reply.ok();
-            }
-        } else {
-            reply.error(ENOENT);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(code: u16, reason: &str) -> anyhow::Error {
+        KubeClientError::Api {
+            code,
+            reason: reason.to_string(),
+            message: String::new(),
         }
+        .into()
+    }
+
+    #[test]
+    fn test_slice_is_bounded() {
+        let data = b"apiVersion: v1";
+
+        assert_eq!(slice(data, 0, 3), b"api");
+        assert_eq!(slice(data, 12, 100), b"v1");
+        assert_eq!(slice(data, 14, 10), b"");
+        assert_eq!(slice(data, 1000, 10), b"");
+    }
+
+    #[test]
+    fn test_errno_for_api_status() {
+        assert_eq!(errno(&api_error(403, "Forbidden")), EACCES);
+        assert_eq!(errno(&api_error(404, "NotFound")), ENOENT);
+        assert_eq!(errno(&api_error(409, "AlreadyExists")), EEXIST);
+        assert_eq!(errno(&api_error(409, "Conflict")), EBUSY);
+        assert_eq!(errno(&api_error(422, "Invalid")), EINVAL);
+        assert_eq!(errno(&api_error(500, "InternalError")), EIO);
+    }
+
+    #[test]
+    fn test_errno_for_transport_failures() {
+        let timeout: anyhow::Error = KubeClientError::Timeout(String::new()).into();
+        let refused: anyhow::Error = KubeClientError::Connection(String::new()).into();
+        let unreadable: anyhow::Error = KubeClientError::Decode(String::new()).into();
+        let invalid: anyhow::Error = KubeClientError::Request(String::new()).into();
+
+        assert_eq!(errno(&timeout), ETIMEDOUT);
+        assert_eq!(errno(&refused), ECONNREFUSED);
+        assert_eq!(errno(&unreadable), EIO);
+        assert_eq!(errno(&invalid), EINVAL);
+    }
+
+    #[test]
+    fn test_errno_for_local_failures() {
+        let missing: anyhow::Error = KubeFSInodeError::MissingInode.into();
+        let invalid: anyhow::Error = serde_yaml::from_str::<serde_json::Value>("a: [")
+            .unwrap_err()
+            .into();
+        let unsupported: anyhow::Error = std::io::Error::from_raw_os_error(EPERM).into();
+
+        assert_eq!(errno(&missing), ENOENT);
+        assert_eq!(errno(&invalid), EINVAL);
+        assert_eq!(errno(&unsupported), EPERM);
     }
 }
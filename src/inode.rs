@@ -1,39 +1,105 @@
+use crate::{
+    data,
+    pod::{self, FollowedLog},
+};
+use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, VecDeque},
     error::Error,
     fmt::{self, Display},
+    sync::{Arc, Mutex, MutexGuard},
 };
 
 #[derive(Debug, Clone, Copy)]
 pub enum KubeFSLevel {
     Root,
+    Context,
     Namespace,
+    Cluster,
     Object,
     File,
+    /// An object served as a directory rather than a file, such as a pod
+    ObjectDirectory,
+    /// The manifest file of an object directory
+    Manifest,
+    LogDirectory,
+    Log,
+    ContainersDirectory,
+    Container,
+    /// A file holding one value from the spec or status of a container
+    ContainerFile,
+    /// Runs the command line written to it in its container
+    Exec,
+    /// Output of the last command run through the `exec` file next to it
+    ExecOutput,
+    ContainerFsDirectory,
+    /// The root directory of a container's filesystem
+    ContainerFsRoot,
+    ContainerFsDir,
+    ContainerFsFile,
+    /// A key of a ConfigMap or Secret, holding its decoded value
+    DataKey,
+    ErrorLog,
 }
 
-const MAX_SUPPORTED_NAMESPACES: u64 = 10000;
+/// Reserved directory under the root holding cluster-scoped kinds. Namespace
+/// names are DNS labels so they can never start with an underscore.
+pub const CLUSTER_DIRECTORY: &str = "_cluster";
+
+/// Read-only file under the root listing recent failures with the full
+/// message returned by the API server. Namespace names cannot start with a
+/// dot, so the name never clashes.
+pub const ERROR_LOG_FILE: &str = ".kubefs-errors";
+
+const MAX_ERROR_LOG_ENTRIES: usize = 100;
+
+pub const MANIFEST_FILE: &str = "manifest";
+
+pub const LOG_DIRECTORY: &str = "logs";
+
+pub const CONTAINERS_DIRECTORY: &str = "containers";
+
+pub const EXEC_FILE: &str = "exec";
+
+pub const EXEC_OUTPUT_FILE: &str = "output";
 
-const KUBEFS_OBJECTS: [&str; 7] = [
-    "deployments",
-    "services",
-    "pods",
-    "statefulsets",
-    "configmaps",
-    "secrets",
-    "serviceaccounts",
-];
+pub const CONTAINER_FS_DIRECTORY: &str = "fs";
+
+const POD_KIND: &str = "pods";
+
+/// Level of the entries in a kind directory. Pods, ConfigMaps and Secrets
+/// are served as directories.
+fn object_level(kind: &str) -> KubeFSLevel {
+    match kind {
+        POD_KIND => KubeFSLevel::ObjectDirectory,
+        _ if data::has_keys(kind) => KubeFSLevel::ObjectDirectory,
+        _ => KubeFSLevel::File,
+    }
+}
+
+const ROOT_INO: u64 = 1;
 
 #[derive(Debug)]
 pub enum KubeFSInodeError {
     MissingInode,
+    /// A namespace left out of the mount by the namespace filter
+    ExcludedNamespace(String),
+    /// A command run in a container to serve its filesystem failed, with
+    /// what it wrote to stderr
+    ContainerCommand(String),
 }
 
 impl Error for KubeFSInodeError {}
 
 impl Display for KubeFSInodeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Missing Inode")
+        match self {
+            KubeFSInodeError::MissingInode => write!(f, "Missing Inode"),
+            KubeFSInodeError::ExcludedNamespace(namespace) => {
+                write!(f, "Namespace {} is excluded from the mount", namespace)
+            }
+            KubeFSInodeError::ContainerCommand(message) => write!(f, "{}", message),
+        }
     }
 }
 
@@ -45,132 +111,419 @@ pub struct KubeFSInode {
     pub level: KubeFSLevel,
 }
 
-pub trait K8sInteractions {
-    fn get_namespaces(&mut self) -> Result<Vec<String>, anyhow::Error>;
+/// An object returned when listing a kind.
+#[derive(Debug, Clone)]
+pub struct KubeObjectEntry {
+    pub name: String,
+    pub uid: Option<String>,
+    pub stat: ObjectStat,
+}
+
+/// What a command run in a container wrote and whether it succeeded.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub success: bool,
+}
+
+impl ExecOutput {
+    /// stdout followed by stderr, as text.
+    pub fn combined(&self) -> String {
+        let mut text = String::from_utf8_lossy(&self.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&self.stderr));
+
+        text
+    }
+}
+
+/// An entry in the filesystem of a container.
+struct ContainerPath {
+    pod: String,
+    namespace: String,
+    container: String,
+    path: String,
+}
+
+impl ContainerPath {
+    fn join(&self, name: &str) -> String {
+        format!("{}/{}", self.path.trim_end_matches('/'), name)
+    }
+}
+
+/// What `getattr` reports for an object file. Times are seconds since the
+/// epoch and the size is the length of the rendered manifest.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectStat {
+    pub size: u64,
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub resource_version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct InodeKey {
+    parent: u64,
+    name: String,
+    uid: Option<String>,
+}
+
+/// Hands out inode numbers keyed by the path of an entry, and for objects by
+/// their UID, so an entry keeps its number for as long as the kernel knows
+/// about it and a recreated object never reuses the number of its
+/// predecessor.
+struct InodeAllocator {
+    next_ino: u64,
+    inos: HashMap<InodeKey, u64>,
+    keys: HashMap<u64, InodeKey>,
+}
+
+impl InodeAllocator {
+    fn new() -> Self {
+        InodeAllocator {
+            next_ino: ROOT_INO + 1,
+            inos: HashMap::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    fn allocate(&mut self, key: InodeKey) -> u64 {
+        if let Some(ino) = self.inos.get(&key) {
+            return *ino;
+        }
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+
+        self.inos.insert(key.clone(), ino);
+        self.keys.insert(ino, key);
+
+        ino
+    }
+
+    fn release(&mut self, ino: &u64) {
+        if let Some(key) = self.keys.remove(ino) {
+            self.inos.remove(&key);
+        }
+    }
+
+    /// Moves an inode number to a new key, as a rename keeps the inode.
+    fn rekey(&mut self, ino: &u64, key: InodeKey) {
+        if let Some(old) = self.keys.insert(*ino, key.clone()) {
+            self.inos.remove(&old);
+        }
+        self.inos.insert(key, *ino);
+    }
+}
+
+/// How objects removed with `rm` or `rmdir` are deleted, taken from the mount
+/// options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeleteOptions {
+    pub propagation_policy: Option<String>,
+    pub grace_period_seconds: Option<u32>,
+}
+
+/// Namespaces shown in the mount, taken from the command line. Patterns may
+/// use `*` as a wildcard. An empty include list allows every namespace.
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl NamespaceFilter {
+    pub fn allows(&self, namespace: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, namespace)))
+            && !self.exclude.iter().any(|p| glob_match(p, namespace))
+    }
+
+    /// The allowed namespaces when every one of them is named exactly. Users
+    /// limited to a few namespaces are usually not allowed to list
+    /// namespaces, so these are shown without asking the API server.
+    fn literal_namespaces(&self) -> Option<Vec<String>> {
+        if self.include.is_empty() || self.include.iter().any(|p| p.contains('*')) {
+            return None;
+        }
+
+        Some(
+            self.include
+                .iter()
+                .filter(|namespace| self.allows(namespace))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == name,
+        Some(i) => {
+            let (prefix, rest) = (&pattern[..i], &pattern[i + 1..]);
+            if !name.starts_with(prefix) {
+                return false;
+            }
+
+            let tail = &name[prefix.len()..];
+            tail.char_indices()
+                .map(|(j, _)| &tail[j..])
+                .chain(std::iter::once(""))
+                .any(|tail| glob_match(rest, tail))
+        }
+    }
+}
+
+/// Calls made to the API server. Implementations are shared by every
+/// request thread, so they must be safe to call concurrently.
+pub trait K8sInteractions: Send + Sync {
+    fn get_namespaces(&self) -> Result<Vec<String>, anyhow::Error>;
+    fn get_object_kinds(&self, namespaced: bool) -> Result<Vec<String>, anyhow::Error>;
     fn get_objects(
-        &mut self,
-        namespace: &str,
+        &self,
+        namespace: Option<&str>,
         object_name: &str,
-    ) -> Result<Vec<String>, anyhow::Error>;
+    ) -> Result<Vec<KubeObjectEntry>, anyhow::Error>;
+    /// Replaces an object with a manifest read at `resource_version`, if
+    /// known, which a neat manifest is sent with so that changes made since
+    /// are refused rather than overwritten.
     fn update_object(
-        &mut self,
+        &self,
         name: &str,
-        namespace: &str,
+        namespace: Option<&str>,
         object_name: &str,
         data: &str,
-    ) -> Result<(), anyhow::Error>;
+        resource_version: Option<&str>,
+    ) -> anyhow::Result<KubeObjectEntry>;
+    /// Applies a JSON merge patch to an object.
+    fn patch_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+        patch: &Value,
+    ) -> anyhow::Result<KubeObjectEntry>;
+    fn get_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+    ) -> anyhow::Result<Value>;
+    /// The manifest served for an object, with the resourceVersion it was
+    /// rendered from.
     fn get_object_data_as_yaml(
-        &mut self,
+        &self,
         name: &str,
-        namespace: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+    ) -> anyhow::Result<(String, Option<String>)>;
+    fn create_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+        data: &str,
+    ) -> anyhow::Result<KubeObjectEntry>;
+    fn delete_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
         object_name: &str,
+        options: &DeleteOptions,
+    ) -> anyhow::Result<()>;
+    fn get_logs(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        previous: bool,
     ) -> anyhow::Result<String>;
-    fn create_namespace(&mut self, name: &str) -> anyhow::Result<()>;
-    fn remove_namespace(&mut self, name: &str) -> anyhow::Result<()>;
+    /// Fills `log` with the output of a container so far and keeps
+    /// appending to it until it is closed.
+    fn follow_logs(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        log: Arc<FollowedLog>,
+    ) -> anyhow::Result<()>;
+    fn create_namespace(&self, name: &str) -> anyhow::Result<()>;
+    fn remove_namespace(&self, name: &str) -> anyhow::Result<()>;
+    /// Runs a command in a container, feeding it `stdin`.
+    fn exec(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        command: &[&str],
+        stdin: &[u8],
+    ) -> anyhow::Result<ExecOutput>;
 }
 
-pub struct KubeFSINodes {
-    pub inodes: HashMap<u64, KubeFSInode>,
-    client: Box<dyn K8sInteractions>,
+/// The clusters served by a mount. A single cluster is served from the root,
+/// several are served from top-level directories named after their
+/// kubeconfig contexts.
+enum Clusters {
+    Single(Box<dyn K8sInteractions>),
+    Contexts(BTreeMap<String, Box<dyn K8sInteractions>>),
 }
 
-impl KubeFSINodes {
-    pub fn new(client: Box<dyn K8sInteractions>) -> Self {
-        let mut inodes = HashMap::new();
-        inodes.insert(
-            1,
-            KubeFSInode {
-                ino: 1,
-                parent: None,
-                name: String::from("Root"),
-                level: KubeFSLevel::Root,
-            },
-        );
+/// The inode table proper. It is only touched under the lock in
+/// `KubeFSINodes`, which is never held across a call to the API server.
+struct InodeTable {
+    inodes: HashMap<u64, KubeFSInode>,
+    allocator: InodeAllocator,
+    lookups: HashMap<u64, u64>,
+    errors: VecDeque<String>,
+    stats: HashMap<u64, ObjectStat>,
+    /// Output of the last exec, keyed by container directory
+    exec_outputs: HashMap<u64, String>,
+}
 
-        KubeFSINodes {
-            inodes: inodes,
-            client: client,
+impl InodeTable {
+    fn get_inode(&self, ino: &u64) -> Option<&KubeFSInode> {
+        self.inodes.get(ino)
+    }
+
+    fn allocate_ino(&mut self, parent: &u64, name: &str) -> u64 {
+        self.allocator.allocate(InodeKey {
+            parent: *parent,
+            name: name.to_string(),
+            uid: None,
+        })
+    }
+
+    fn insert_child(&mut self, parent: &u64, name: &str, level: KubeFSLevel) -> KubeFSInode {
+        self.insert_child_with_uid(parent, name, None, level)
+    }
+
+    /// Adds a single child under `parent`, reusing the inode number already
+    /// allocated to the same path if there is one.
+    fn insert_child_with_uid(
+        &mut self,
+        parent: &u64,
+        name: &str,
+        uid: Option<String>,
+        level: KubeFSLevel,
+    ) -> KubeFSInode {
+        let ino = self.allocator.allocate(InodeKey {
+            parent: *parent,
+            name: name.to_string(),
+            uid,
+        });
+
+        let inode = KubeFSInode {
+            ino,
+            parent: Some(*parent),
+            name: name.to_string(),
+            level,
+        };
+        self.inodes.insert(ino, inode.clone());
+
+        inode
+    }
+
+    /// Replaces the children of `parent` with a fresh listing. Entries which
+    /// are still present keep their inode numbers, entries which disappeared
+    /// are dropped along with everything below them.
+    fn replace_children(
+        &mut self,
+        parent: &u64,
+        children: Vec<(String, Option<String>, KubeFSLevel)>,
+    ) -> Vec<u64> {
+        let mut current = vec![];
+
+        for (name, uid, level) in children {
+            current.push(self.insert_child_with_uid(parent, &name, uid, level).ino);
+        }
+
+        let stale: Vec<u64> = self
+            .find_inode_by_parent(parent)
+            .iter()
+            .map(|inode| inode.ino)
+            .filter(|ino| !current.contains(ino))
+            .collect();
+
+        for ino in stale {
+            self.remove_inode(&ino);
         }
+
+        current
     }
 
-    pub fn get_inode(&self, ino: &u64) -> Option<&KubeFSInode> {
-        self.inodes.get(ino)
+    /// Stores the stat of an object. Kinds without managedFields carry no
+    /// modification time, so a change of resourceVersion since the object
+    /// was last seen stands in for one.
+    fn update_stat(&mut self, ino: &u64, mut stat: ObjectStat) {
+        if stat.modified.is_none() {
+            stat.modified = match self.stats.get(ino) {
+                Some(previous) if previous.resource_version == stat.resource_version => {
+                    previous.modified
+                }
+                Some(_) => Some(time::get_time().sec),
+                None => stat.created,
+            };
+        }
+
+        self.stats.insert(*ino, stat);
     }
 
-    pub fn fetch_child_nodes_for_node(&mut self, ino: &u64) -> anyhow::Result<()> {
-        let inode = self
-            .inodes
-            .get(ino)
-            .ok_or(KubeFSInodeError::MissingInode)?
-            .clone();
+    fn get_stat(&self, ino: &u64) -> Option<ObjectStat> {
+        let inode = self.get_inode(ino)?;
 
         match inode.level {
-            KubeFSLevel::Root => {
-                // Delete all namespace nodes
-                self.delete_by_parent_ino(&inode.ino);
-                // Fetch all namespaces
-                let namespaces = self.client.get_namespaces()?;
-
-                // Add Namespace inodes
-                for (i, ns) in namespaces.iter().enumerate() {
-                    self.inodes.insert(
-                        (i + 2) as u64,
-                        KubeFSInode {
-                            ino: (i + 2) as u64,
-                            name: ns.clone(),
-                            parent: Some(inode.ino),
-                            level: KubeFSLevel::Namespace,
-                        },
-                    );
-                }
-            }
-            KubeFSLevel::Namespace => {
-                self.delete_by_parent_ino(&inode.ino);
-
-                for (i, o) in KUBEFS_OBJECTS.iter().enumerate() {
-                    self.inodes.insert(
-                        MAX_SUPPORTED_NAMESPACES + (i as u64),
-                        KubeFSInode {
-                            ino: MAX_SUPPORTED_NAMESPACES + (i as u64),
-                            name: o.to_string(),
-                            parent: Some(inode.ino),
-                            level: KubeFSLevel::Object,
-                        },
-                    );
-                }
-            }
-            KubeFSLevel::Object => {
-                self.delete_by_parent_ino(&inode.ino);
+            KubeFSLevel::ErrorLog => Some(ObjectStat {
+                size: self.errors.iter().map(|e| e.len() as u64).sum(),
+                ..ObjectStat::default()
+            }),
+            // An object directory carries the stat of its manifest
+            KubeFSLevel::Manifest => self.stats.get(&inode.parent?).cloned(),
+            _ => self.stats.get(ino).cloned(),
+        }
+    }
 
-                let parent_ino = inode.parent.ok_or(KubeFSInodeError::MissingInode)?;
-                let namespace_inode = self
-                    .inodes
-                    .get(&parent_ino)
-                    .ok_or(KubeFSInodeError::MissingInode)?;
-                let namespace_name = &namespace_inode.name;
-                let object_name = &inode.name;
-
-                let objects = self.client.get_objects(namespace_name, object_name)?;
-
-                for (i, o) in objects.iter().enumerate() {
-                    self.inodes.insert(
-                        MAX_SUPPORTED_NAMESPACES + (KUBEFS_OBJECTS.len() + i) as u64,
-                        KubeFSInode {
-                            ino: MAX_SUPPORTED_NAMESPACES + (KUBEFS_OBJECTS.len() + i) as u64,
-                            name: o.clone(),
-                            parent: Some(inode.ino),
-                            level: KubeFSLevel::File,
-                        },
-                    );
-                }
+    fn forget(&mut self, ino: &u64, nlookup: u64) {
+        let remaining = match self.lookups.get_mut(ino) {
+            Some(count) => {
+                *count = count.saturating_sub(nlookup);
+                *count
             }
-            KubeFSLevel::File => {}
+            None => 0,
+        };
+
+        if remaining == 0 && *ino != ROOT_INO {
+            self.lookups.remove(ino);
+            self.remove_inode(ino);
         }
+    }
 
-        Ok(())
+    fn remove_inode(&mut self, ino: &u64) {
+        self.remove_children(ino);
+        self.inodes.remove(ino);
+        self.release_unreferenced(ino);
+
+        let inodes = &self.inodes;
+        self.stats.retain(|ino, _| inodes.contains_key(ino));
+        self.exec_outputs.retain(|ino, _| inodes.contains_key(ino));
     }
 
-    pub fn find_inode_by_parent(&self, parent: &u64) -> Vec<KubeFSInode> {
+    fn remove_children(&mut self, parent: &u64) {
+        for child in self.find_inode_by_parent(parent) {
+            self.remove_children(&child.ino);
+            self.release_unreferenced(&child.ino);
+        }
+
+        self.delete_by_parent_ino(parent);
+    }
+
+    /// Numbers the kernel still references stay reserved until forgotten.
+    fn release_unreferenced(&mut self, ino: &u64) {
+        if !self.lookups.contains_key(ino) {
+            self.allocator.release(ino);
+        }
+    }
+
+    fn find_inode_by_parent(&self, parent: &u64) -> Vec<KubeFSInode> {
         self.inodes
             .values()
             .filter(|inode| inode.parent == Some(*parent))
@@ -178,407 +531,2060 @@ impl KubeFSINodes {
             .collect()
     }
 
-    pub fn lookup_inode_by_parent_and_name(&self, parent: &u64, name: &str) -> Option<KubeFSInode> {
+    fn lookup_inode_by_parent_and_name(&self, parent: &u64, name: &str) -> Option<KubeFSInode> {
         self.inodes
             .values()
-            .filter(|inode| inode.parent == Some(*parent) && inode.name == name)
+            .find(|inode| inode.parent == Some(*parent) && inode.name == name)
             .cloned()
-            .nth(0)
     }
 
-    pub fn get_file_contents(&mut self, ino: &u64) -> anyhow::Result<String> {
-        let inode = self
-            .get_inode(&ino)
+    /// Returns the path of an inode relative to the mount point.
+    fn get_path(&self, ino: &u64) -> String {
+        let mut names = vec![];
+        let mut current = self.get_inode(ino);
+
+        while let Some(inode) = current {
+            if inode.parent.is_none() {
+                break;
+            }
+
+            names.push(inode.name.clone());
+            current = inode.parent.and_then(|parent| self.get_inode(&parent));
+        }
+
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    /// Returns the name of the context directory an inode sits under.
+    fn get_context(&self, ino: &u64) -> Option<String> {
+        let mut current = self.get_inode(ino);
+
+        while let Some(inode) = current {
+            if let KubeFSLevel::Context = inode.level {
+                return Some(inode.name.clone());
+            }
+
+            current = inode.parent.and_then(|parent| self.get_inode(&parent));
+        }
+
+        None
+    }
+
+    /// Returns the namespace a kind directory belongs to, or `None` when it
+    /// sits under the cluster-scoped directory.
+    fn get_namespace_of_kind(&self, kind: &KubeFSInode) -> anyhow::Result<Option<String>> {
+        let parent = self
+            .get_inode(&kind.parent.ok_or(KubeFSInodeError::MissingInode)?)
+            .ok_or(KubeFSInodeError::MissingInode)?;
+
+        match parent.level {
+            KubeFSLevel::Namespace => Ok(Some(parent.name.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the kind directory of an object file along with its namespace.
+    fn get_kind_of_file(
+        &self,
+        file: &KubeFSInode,
+    ) -> anyhow::Result<(KubeFSInode, Option<String>)> {
+        let kind = self
+            .get_inode(&file.parent.ok_or(KubeFSInodeError::MissingInode)?)
             .ok_or(KubeFSInodeError::MissingInode)?
             .clone();
+        let namespace = self.get_namespace_of_kind(&kind)?;
 
-        match inode.level {
-            KubeFSLevel::File => {
-                let object = self
-                    .get_inode(&inode.parent.ok_or(KubeFSInodeError::MissingInode)?)
-                    .ok_or(KubeFSInodeError::MissingInode)?
-                    .clone();
+        Ok((kind, namespace))
+    }
 
-                let namespace = self
+    /// Returns the object an entry belongs to, which is an object file or
+    /// an object directory, along with its kind directory and namespace.
+    fn get_object_of(
+        &self,
+        inode: &KubeFSInode,
+    ) -> anyhow::Result<(KubeFSInode, KubeFSInode, Option<String>)> {
+        let mut object = inode.clone();
+
+        while !matches!(
+            object.level,
+            KubeFSLevel::File | KubeFSLevel::ObjectDirectory
+        ) {
+            object = match object.level {
+                KubeFSLevel::Manifest
+                | KubeFSLevel::LogDirectory
+                | KubeFSLevel::Log
+                | KubeFSLevel::ContainersDirectory
+                | KubeFSLevel::Container
+                | KubeFSLevel::ContainerFile
+                | KubeFSLevel::Exec
+                | KubeFSLevel::ExecOutput
+                | KubeFSLevel::ContainerFsDirectory
+                | KubeFSLevel::ContainerFsRoot
+                | KubeFSLevel::ContainerFsDir
+                | KubeFSLevel::ContainerFsFile
+                | KubeFSLevel::DataKey => self
                     .get_inode(&object.parent.ok_or(KubeFSInodeError::MissingInode)?)
                     .ok_or(KubeFSInodeError::MissingInode)?
-                    .clone();
+                    .clone(),
+                _ => return Err(KubeFSInodeError::MissingInode.into()),
+            };
+        }
 
-                let data = self.client.get_object_data_as_yaml(
-                    &inode.name,
-                    &namespace.name,
-                    &object.name,
-                )?;
+        let (kind, namespace) = self.get_kind_of_file(&object)?;
 
-                Ok(data)
-            }
-            _ => Ok(String::new()),
+        Ok((object, kind, namespace))
+    }
+
+    fn delete_by_parent_ino(&mut self, parent: &u64) {
+        self.inodes.retain(|_, inode| inode.parent != Some(*parent))
+    }
+}
+
+pub struct KubeFSINodes {
+    table: Mutex<InodeTable>,
+    clusters: Clusters,
+    delete_options: DeleteOptions,
+    namespace_filter: NamespaceFilter,
+}
+
+impl KubeFSINodes {
+    pub fn new(client: Box<dyn K8sInteractions>) -> Self {
+        Self::with_clusters(Clusters::Single(client))
+    }
+
+    /// Serves each cluster from a top-level directory named after its
+    /// context.
+    pub fn with_contexts(clients: Vec<(String, Box<dyn K8sInteractions>)>) -> Self {
+        Self::with_clusters(Clusters::Contexts(clients.into_iter().collect()))
+    }
+
+    fn with_clusters(clusters: Clusters) -> Self {
+        let mut inodes = HashMap::new();
+        inodes.insert(
+            ROOT_INO,
+            KubeFSInode {
+                ino: ROOT_INO,
+                parent: None,
+                name: String::from("Root"),
+                level: KubeFSLevel::Root,
+            },
+        );
+
+        KubeFSINodes {
+            table: Mutex::new(InodeTable {
+                inodes,
+                allocator: InodeAllocator::new(),
+                lookups: HashMap::new(),
+                errors: VecDeque::new(),
+                stats: HashMap::new(),
+                exec_outputs: HashMap::new(),
+            }),
+            clusters,
+            delete_options: DeleteOptions::default(),
+            namespace_filter: NamespaceFilter::default(),
         }
     }
 
-    pub fn create_object(
-        &mut self,
-        name: &str,
-        parent_ino: &u64,
-        _data: &[u8],
-    ) -> anyhow::Result<()> {
-        let inode = self
-            .get_inode(&parent_ino)
-            .ok_or(KubeFSInodeError::MissingInode)?
-            .clone();
+    pub fn with_delete_options(mut self, delete_options: DeleteOptions) -> Self {
+        self.delete_options = delete_options;
+        self
+    }
+
+    pub fn with_namespace_filter(mut self, namespace_filter: NamespaceFilter) -> Self {
+        self.namespace_filter = namespace_filter;
+        self
+    }
+
+    fn table(&self) -> MutexGuard<'_, InodeTable> {
+        // Every update leaves the table consistent, so a panic on another
+        // request thread is no reason to stop serving this one
+        self.table.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get_inode(&self, ino: &u64) -> Option<KubeFSInode> {
+        self.table().get_inode(ino).cloned()
+    }
+
+    /// Returns the client for the cluster an inode belongs to.
+    fn client(&self, ino: &u64) -> anyhow::Result<&dyn K8sInteractions> {
+        match &self.clusters {
+            Clusters::Single(client) => Ok(client.as_ref()),
+            Clusters::Contexts(clients) => self
+                .table()
+                .get_context(ino)
+                .and_then(|context| clients.get(&context))
+                .map(|client| client.as_ref())
+                .ok_or_else(|| KubeFSInodeError::MissingInode.into()),
+        }
+    }
+
+    /// Lists the namespaces of the cluster an inode belongs to which pass the
+    /// namespace filter.
+    fn get_namespaces(&self, ino: &u64) -> anyhow::Result<Vec<String>> {
+        if let Some(namespaces) = self.namespace_filter.literal_namespaces() {
+            return Ok(namespaces);
+        }
+
+        Ok(self
+            .client(ino)?
+            .get_namespaces()?
+            .into_iter()
+            .filter(|namespace| self.namespace_filter.allows(namespace))
+            .collect())
+    }
+
+    fn check_namespace(&self, namespace: &str) -> anyhow::Result<()> {
+        if self.namespace_filter.allows(namespace) {
+            Ok(())
+        } else {
+            Err(KubeFSInodeError::ExcludedNamespace(namespace.to_string()).into())
+        }
+    }
+
+    /// Whether an inode is the directory holding the namespaces of a
+    /// cluster, which is the root unless several contexts are mounted.
+    fn is_cluster_root(&self, inode: &KubeFSInode) -> bool {
+        matches!(
+            (inode.level, &self.clusters),
+            (KubeFSLevel::Context, _) | (KubeFSLevel::Root, Clusters::Single(_))
+        )
+    }
+
+    pub fn fetch_child_nodes_for_node(&self, ino: &u64) -> anyhow::Result<()> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
 
         match inode.level {
-            KubeFSLevel::Root => {
-                self.client.create_namespace(name)?;
+            KubeFSLevel::Root | KubeFSLevel::Context => {
+                let mut children: Vec<(String, Option<String>, KubeFSLevel)> = match &self.clusters
+                {
+                    Clusters::Contexts(clients) if !self.is_cluster_root(&inode) => clients
+                        .keys()
+                        .map(|context| (context.clone(), None, KubeFSLevel::Context))
+                        .collect(),
+                    _ => {
+                        // Fetch all namespaces
+                        let namespaces = self.get_namespaces(ino)?;
+
+                        let mut children: Vec<(String, Option<String>, KubeFSLevel)> = namespaces
+                            .into_iter()
+                            .map(|ns| (ns, None, KubeFSLevel::Namespace))
+                            .collect();
+                        children.push((
+                            String::from(CLUSTER_DIRECTORY),
+                            None,
+                            KubeFSLevel::Cluster,
+                        ));
+                        children
+                    }
+                };
+
+                if let KubeFSLevel::Root = inode.level {
+                    children.push((String::from(ERROR_LOG_FILE), None, KubeFSLevel::ErrorLog));
+                }
+
+                self.table().replace_children(&inode.ino, children);
             }
-            _ => {}
-        };
+            KubeFSLevel::Namespace | KubeFSLevel::Cluster => {
+                let namespaced = matches!(inode.level, KubeFSLevel::Namespace);
+                let kinds = self.client(ino)?.get_object_kinds(namespaced)?;
+
+                let children = kinds
+                    .into_iter()
+                    .map(|k| (k, None, KubeFSLevel::Object))
+                    .collect();
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::Object => {
+                let namespace = self.table().get_namespace_of_kind(&inode)?;
+
+                let objects = self
+                    .client(ino)?
+                    .get_objects(namespace.as_deref(), &inode.name)?;
+
+                let stats: Vec<ObjectStat> = objects.iter().map(|o| o.stat.clone()).collect();
+                let children = objects
+                    .into_iter()
+                    .map(|o| (o.name, o.uid, object_level(&inode.name)))
+                    .collect();
+
+                let mut table = self.table();
+                let inos = table.replace_children(&inode.ino, children);
+                for (ino, stat) in inos.into_iter().zip(stats) {
+                    table.update_stat(&ino, stat);
+                }
+            }
+            KubeFSLevel::ObjectDirectory => {
+                let (object, kind, namespace) = self.table().get_object_of(&inode)?;
+                if data::has_keys(&kind.name) {
+                    let object = self.client(ino)?.get_object(
+                        &object.name,
+                        namespace.as_deref(),
+                        &kind.name,
+                    )?;
+                    return self.replace_key_files(&inode, &kind.name, &object);
+                }
+
+                let children = vec![
+                    (String::from(MANIFEST_FILE), None, KubeFSLevel::Manifest),
+                    (String::from(LOG_DIRECTORY), None, KubeFSLevel::LogDirectory),
+                    (
+                        String::from(CONTAINERS_DIRECTORY),
+                        None,
+                        KubeFSLevel::ContainersDirectory,
+                    ),
+                    (
+                        String::from(CONTAINER_FS_DIRECTORY),
+                        None,
+                        KubeFSLevel::ContainerFsDirectory,
+                    ),
+                ];
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::LogDirectory => {
+                let (pod, kind, namespace) = self.table().get_object_of(&inode)?;
+
+                let pod =
+                    self.client(ino)?
+                        .get_object(&pod.name, namespace.as_deref(), &kind.name)?;
+                let children = pod::log_files(&pod)
+                    .into_iter()
+                    .map(|name| (name, None, KubeFSLevel::Log))
+                    .collect();
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::ContainersDirectory | KubeFSLevel::ContainerFsDirectory => {
+                let (pod, kind, namespace) = self.table().get_object_of(&inode)?;
+                let level = match inode.level {
+                    KubeFSLevel::ContainersDirectory => KubeFSLevel::Container,
+                    _ => KubeFSLevel::ContainerFsRoot,
+                };
+
+                let pod =
+                    self.client(ino)?
+                        .get_object(&pod.name, namespace.as_deref(), &kind.name)?;
+                let children = pod::container_names(&pod)
+                    .into_iter()
+                    .map(|name| (name, None, level))
+                    .collect();
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::Container => {
+                let mut children: Vec<_> = pod::CONTAINER_FILES
+                    .iter()
+                    .map(|name| (name.to_string(), None, KubeFSLevel::ContainerFile))
+                    .collect();
+                children.push((String::from(EXEC_FILE), None, KubeFSLevel::Exec));
+                children.push((
+                    String::from(EXEC_OUTPUT_FILE),
+                    None,
+                    KubeFSLevel::ExecOutput,
+                ));
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::ContainerFsRoot | KubeFSLevel::ContainerFsDir => {
+                let directory = self.container_path(&inode)?;
+                let listing = self.run_in_container(
+                    ino,
+                    &directory,
+                    &["sh", "-c", pod::LIST_DIRECTORY, "sh", &directory.path],
+                    &[],
+                )?;
+
+                let children = pod::parse_listing(&String::from_utf8_lossy(&listing))
+                    .into_iter()
+                    .map(|(name, is_dir)| {
+                        let level = if is_dir {
+                            KubeFSLevel::ContainerFsDir
+                        } else {
+                            KubeFSLevel::ContainerFsFile
+                        };
+                        (name, None, level)
+                    })
+                    .collect();
+
+                self.table().replace_children(&inode.ino, children);
+            }
+            KubeFSLevel::File
+            | KubeFSLevel::Manifest
+            | KubeFSLevel::Log
+            | KubeFSLevel::ContainerFile
+            | KubeFSLevel::Exec
+            | KubeFSLevel::ExecOutput
+            | KubeFSLevel::ContainerFsFile
+            | KubeFSLevel::DataKey
+            | KubeFSLevel::ErrorLog => {}
+        }
 
         Ok(())
     }
 
-    pub fn update_object(&mut self, ino: &u64, data: &str) -> anyhow::Result<()> {
-        let inode = self
-            .get_inode(&ino)
-            .ok_or(KubeFSInodeError::MissingInode)?
-            .clone();
+    /// Reserves an inode number for an entry which is not backed by a
+    /// Kubernetes object, such as an editor swap file.
+    pub fn allocate_ino(&self, parent: &u64, name: &str) -> u64 {
+        self.table().allocate_ino(parent, name)
+    }
 
-        match inode.level {
-            KubeFSLevel::File => {
-                let object = self
-                    .get_inode(&inode.parent.ok_or(KubeFSInodeError::MissingInode)?)
+    pub fn release_ino(&self, ino: &u64) {
+        self.table().allocator.release(ino);
+    }
+
+    /// Moves an inode number allocated with `allocate_ino` to a new path.
+    pub fn rename_ino(&self, ino: &u64, parent: &u64, name: &str) {
+        self.table().allocator.rekey(
+            ino,
+            InodeKey {
+                parent: *parent,
+                name: name.to_string(),
+                uid: None,
+            },
+        );
+    }
+
+    pub fn get_stat(&self, ino: &u64) -> Option<ObjectStat> {
+        self.table().get_stat(ino)
+    }
+
+    /// Records that the kernel holds one more reference to `ino`.
+    pub fn increment_lookup(&self, ino: &u64) {
+        *self.table().lookups.entry(*ino).or_insert(0) += 1;
+    }
+
+    /// Handles a FUSE forget. Once the kernel drops every reference to an
+    /// inode nothing can observe its number any more, so it is released and
+    /// will be fetched again on the next lookup.
+    pub fn forget(&self, ino: &u64, nlookup: u64) {
+        self.table().forget(ino, nlookup);
+    }
+
+    pub fn find_inode_by_parent(&self, parent: &u64) -> Vec<KubeFSInode> {
+        self.table().find_inode_by_parent(parent)
+    }
+
+    pub fn lookup_inode_by_parent_and_name(&self, parent: &u64, name: &str) -> Option<KubeFSInode> {
+        self.table().lookup_inode_by_parent_and_name(parent, name)
+    }
+
+    /// Contents of a file as bytes. Files in containers are read as they
+    /// are, everything else is text.
+    pub fn get_file_bytes(&self, ino: &u64) -> anyhow::Result<Vec<u8>> {
+        Ok(self.read_file(ino)?.0)
+    }
+
+    /// Contents of a file, with the resourceVersion of the object they were
+    /// read from for manifests. Writing them back passes that version on.
+    pub fn read_file(&self, ino: &u64) -> anyhow::Result<(Vec<u8>, Option<String>)> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
+
+        let data = match inode.level {
+            KubeFSLevel::ContainerFsFile => {
+                let file = self.container_path(&inode)?;
+                self.run_in_container(ino, &file, &["cat", "--", &file.path], &[])?
+            }
+            KubeFSLevel::DataKey => {
+                let (object, kind, namespace) = self.table().get_object_of(&inode)?;
+                let object =
+                    self.client(ino)?
+                        .get_object(&object.name, namespace.as_deref(), &kind.name)?;
+
+                data::value(&kind.name, &object, &inode.name)?
                     .ok_or(KubeFSInodeError::MissingInode)?
-                    .clone();
+            }
+            KubeFSLevel::File | KubeFSLevel::Manifest => {
+                let (data, resource_version) = self.get_manifest(&inode)?;
+                return Ok((data.into_bytes(), resource_version));
+            }
+            _ => self.get_file_contents(ino)?.into_bytes(),
+        };
+
+        Ok((data, None))
+    }
+
+    /// Writes the contents of a file, uploading files in containers,
+    /// patching single keys and updating objects otherwise. Manifests read
+    /// with `read_file` are written with the `resource_version` they were
+    /// read at. Returns the resourceVersion of an updated object.
+    pub fn write_file(
+        &self,
+        ino: &u64,
+        data: Vec<u8>,
+        resource_version: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
+
+        match inode.level {
+            KubeFSLevel::ContainerFsFile => {
+                let file = self.container_path(&inode)?;
+                self.upload(ino, &file.path, &file, &data)?;
+            }
+            KubeFSLevel::DataKey => self.set_key(&inode, &inode.name, Some(&data))?,
+            _ => return self.update_object(ino, &String::from_utf8(data)?, resource_version),
+        }
+
+        Ok(None)
+    }
+
+    /// Creates an empty file where files are more than a local scratch
+    /// copy: in the filesystem of a container, or as a new key of a
+    /// ConfigMap or Secret.
+    pub fn create_file(&self, name: &str, parent_ino: &u64) -> anyhow::Result<KubeFSInode> {
+        let parent = self
+            .get_inode(parent_ino)
+            .ok_or(KubeFSInodeError::MissingInode)?;
+
+        match parent.level {
+            KubeFSLevel::ContainerFsRoot | KubeFSLevel::ContainerFsDir => {
+                let directory = self.container_path(&parent)?;
+                self.upload(parent_ino, &directory.join(name), &directory, &[])?;
+
+                Ok(self
+                    .table()
+                    .insert_child(parent_ino, name, KubeFSLevel::ContainerFsFile))
+            }
+            KubeFSLevel::ObjectDirectory if name != MANIFEST_FILE => {
+                // Only ConfigMaps and Secrets hold files next to the
+                // manifest, the others (pods) hold what is listed for them
+                let (_, kind, _) = self.table().get_object_of(&parent)?;
+                if !data::has_keys(&kind.name) {
+                    return Err(std::io::Error::from_raw_os_error(libc::EPERM).into());
+                }
+
+                self.set_key(&parent, name, Some(&[]))?;
+
+                Ok(self
+                    .table()
+                    .insert_child(parent_ino, name, KubeFSLevel::DataKey))
+            }
+            _ => Err(KubeFSInodeError::MissingInode.into()),
+        }
+    }
+
+    /// Lists the keys of a ConfigMap or Secret next to its manifest, with
+    /// the stat of the object and the size of the value. A key named like
+    /// the manifest is only reachable through the manifest.
+    fn replace_key_files(
+        &self,
+        directory: &KubeFSInode,
+        kind: &str,
+        object: &Value,
+    ) -> anyhow::Result<()> {
+        let mut keys = vec![];
+        for key in data::keys(object) {
+            if key != MANIFEST_FILE {
+                let size = data::value(kind, object, &key)?.map_or(0, |value| value.len());
+                keys.push((key, size as u64));
+            }
+        }
+
+        let mut children = vec![(String::from(MANIFEST_FILE), None, KubeFSLevel::Manifest)];
+        children.extend(
+            keys.iter()
+                .map(|(key, _)| (key.clone(), None, KubeFSLevel::DataKey)),
+        );
+
+        let mut table = self.table();
+        let object_stat = table.get_stat(&directory.ino).unwrap_or_default();
+        let inos = table.replace_children(&directory.ino, children);
+
+        for (ino, (_, size)) in inos.iter().skip(1).zip(keys) {
+            table.stats.insert(
+                *ino,
+                ObjectStat {
+                    size,
+                    ..object_stat.clone()
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sets or removes one key of a ConfigMap or Secret with a merge patch,
+    /// leaving the others as they are. Writing back the value a key is shown
+    /// with changes nothing, so saving a redacted Secret value unedited
+    /// keeps the real one rather than storing the mask.
+    fn set_key(&self, entry: &KubeFSInode, key: &str, value: Option<&[u8]>) -> anyhow::Result<()> {
+        let (object, kind, namespace) = self.table().get_object_of(entry)?;
+        let client = self.client(&entry.ino)?;
+
+        let current = client.get_object(&object.name, namespace.as_deref(), &kind.name)?;
+        if value.is_some() && data::value(&kind.name, &current, key)?.as_deref() == value {
+            return Ok(());
+        }
+        let patch = data::key_patch(&kind.name, &current, key, value);
+        let patched =
+            client.patch_object(&object.name, namespace.as_deref(), &kind.name, &patch)?;
+        self.table().update_stat(&object.ino, patched.stat);
+
+        Ok(())
+    }
+
+    /// The manifest of an object and the resourceVersion it was rendered
+    /// from. Its length is the exact size of the object file.
+    fn get_manifest(&self, inode: &KubeFSInode) -> anyhow::Result<(String, Option<String>)> {
+        let (object, kind, namespace) = self.table().get_object_of(inode)?;
+
+        let (data, resource_version) = self.client(&inode.ino)?.get_object_data_as_yaml(
+            &object.name,
+            namespace.as_deref(),
+            &kind.name,
+        )?;
+
+        self.table().stats.entry(object.ino).or_default().size = data.len() as u64;
+
+        Ok((data, resource_version))
+    }
+
+    pub fn get_file_contents(&self, ino: &u64) -> anyhow::Result<String> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
+
+        match inode.level {
+            KubeFSLevel::File | KubeFSLevel::Manifest => Ok(self.get_manifest(&inode)?.0),
+            KubeFSLevel::Log => {
+                let (pod, _, namespace) = self.table().get_object_of(&inode)?;
+                let (container, previous) = pod::parse_log_file(&inode.name);
+
+                self.client(ino)?.get_logs(
+                    &pod.name,
+                    &namespace.ok_or(KubeFSInodeError::MissingInode)?,
+                    container,
+                    previous,
+                )
+            }
+            KubeFSLevel::ContainerFile => {
+                let (container, (pod, kind, namespace)) = {
+                    let table = self.table();
+                    let container = table
+                        .get_inode(&inode.parent.ok_or(KubeFSInodeError::MissingInode)?)
+                        .ok_or(KubeFSInodeError::MissingInode)?
+                        .name
+                        .clone();
+
+                    (container, table.get_object_of(&inode)?)
+                };
+
+                let pod =
+                    self.client(ino)?
+                        .get_object(&pod.name, namespace.as_deref(), &kind.name)?;
+
+                Ok(pod::container_file(&pod, &container, &inode.name)
+                    .ok_or(KubeFSInodeError::MissingInode)?)
+            }
+            KubeFSLevel::ExecOutput => {
+                let table = self.table();
+                let container = inode.parent.ok_or(KubeFSInodeError::MissingInode)?;
+
+                Ok(table
+                    .exec_outputs
+                    .get(&container)
+                    .cloned()
+                    .unwrap_or_default())
+            }
+            KubeFSLevel::ErrorLog => Ok(self.table().errors.iter().cloned().collect()),
+            _ => Ok(String::new()),
+        }
+    }
+
+    /// Creates a namespace when `parent_ino` is the root, or an object from
+    /// the manifest in `data` when it is a kind directory. `name` is used
+    /// for objects whose manifest does not set `metadata.name`.
+    pub fn create_object(
+        &self,
+        name: &str,
+        parent_ino: &u64,
+        data: &[u8],
+    ) -> anyhow::Result<KubeFSInode> {
+        let inode = self
+            .get_inode(parent_ino)
+            .ok_or(KubeFSInodeError::MissingInode)?;
+
+        match inode.level {
+            KubeFSLevel::Root | KubeFSLevel::Context if self.is_cluster_root(&inode) => {
+                self.check_namespace(name)?;
+                self.client(parent_ino)?.create_namespace(name)?;
+
+                Ok(self
+                    .table()
+                    .insert_child(parent_ino, name, KubeFSLevel::Namespace))
+            }
+            KubeFSLevel::Object => {
+                let namespace = self.table().get_namespace_of_kind(&inode)?;
+                let data = std::str::from_utf8(data)?;
+
+                let created = self.client(parent_ino)?.create_object(
+                    name,
+                    namespace.as_deref(),
+                    &inode.name,
+                    data,
+                )?;
+
+                let mut table = self.table();
+                let inode = table.insert_child_with_uid(
+                    parent_ino,
+                    &created.name,
+                    created.uid,
+                    object_level(&inode.name),
+                );
+                table.update_stat(&inode.ino, created.stat);
+
+                Ok(inode)
+            }
+            KubeFSLevel::ContainerFsRoot | KubeFSLevel::ContainerFsDir => {
+                let directory = self.container_path(&inode)?;
+                let path = directory.join(name);
+                self.run_in_container(parent_ino, &directory, &["mkdir", "--", &path], &[])?;
+
+                Ok(self
+                    .table()
+                    .insert_child(parent_ino, name, KubeFSLevel::ContainerFsDir))
+            }
+            _ => Err(KubeFSInodeError::MissingInode.into()),
+        }
+    }
+
+    pub fn update_object(
+        &self,
+        ino: &u64,
+        data: &str,
+        resource_version: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
+
+        if let KubeFSLevel::File | KubeFSLevel::ObjectDirectory | KubeFSLevel::Manifest =
+            inode.level
+        {
+            let (object, kind, namespace) = self.table().get_object_of(&inode)?;
+
+            let updated = self.client(ino)?.update_object(
+                &object.name,
+                namespace.as_deref(),
+                &kind.name,
+                data,
+                resource_version,
+            )?;
+            let resource_version = updated.stat.resource_version.clone();
+            self.table().update_stat(&object.ino, updated.stat);
+
+            return Ok(resource_version);
+        }
+
+        if let KubeFSLevel::Exec = inode.level {
+            self.exec(&inode, data)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Runs a command line written to an `exec` file and keeps its output
+    /// for the `output` file next to it. Blank writes, such as the
+    /// truncation before a write, run nothing.
+    fn exec(&self, inode: &KubeFSInode, command: &str) -> anyhow::Result<()> {
+        let command = command.trim();
+        if command.is_empty() {
+            return Ok(());
+        }
+
+        let container = inode.parent.ok_or(KubeFSInodeError::MissingInode)?;
+        let (container_name, (pod, _, namespace)) = {
+            let table = self.table();
+            let name = table
+                .get_inode(&container)
+                .ok_or(KubeFSInodeError::MissingInode)?
+                .name
+                .clone();
+
+            (name, table.get_object_of(inode)?)
+        };
+
+        let output = self.client(&inode.ino)?.exec(
+            &pod.name,
+            &namespace.ok_or(KubeFSInodeError::MissingInode)?,
+            &container_name,
+            &["sh", "-c", command],
+            &[],
+        )?;
+        self.table()
+            .exec_outputs
+            .insert(container, output.combined());
+
+        Ok(())
+    }
+
+    pub fn delete_object(&self, name: &str, parent_ino: &u64) -> anyhow::Result<()> {
+        let inode = self
+            .get_inode(parent_ino)
+            .ok_or(KubeFSInodeError::MissingInode)?;
+
+        match inode.level {
+            KubeFSLevel::Root | KubeFSLevel::Context if self.is_cluster_root(&inode) => {
+                self.check_namespace(name)?;
+                self.client(parent_ino)?.remove_namespace(name)?;
+            }
+            KubeFSLevel::Object => {
+                let namespace = self.table().get_namespace_of_kind(&inode)?;
+
+                self.client(parent_ino)?.delete_object(
+                    name,
+                    namespace.as_deref(),
+                    &inode.name,
+                    &self.delete_options,
+                )?;
+            }
+            KubeFSLevel::ContainerFsRoot | KubeFSLevel::ContainerFsDir => {
+                let entry = self
+                    .lookup_inode_by_parent_and_name(parent_ino, name)
+                    .ok_or(KubeFSInodeError::MissingInode)?;
+                let directory = self.container_path(&inode)?;
+                let path = directory.join(name);
+                let remove = match entry.level {
+                    KubeFSLevel::ContainerFsDir => "rmdir",
+                    _ => "rm",
+                };
+
+                self.run_in_container(parent_ino, &directory, &[remove, "--", &path], &[])?;
+                self.table().remove_inode(&entry.ino);
+            }
+            KubeFSLevel::ObjectDirectory => {
+                let entry = self
+                    .lookup_inode_by_parent_and_name(parent_ino, name)
+                    .ok_or(KubeFSInodeError::MissingInode)?;
+                if !matches!(entry.level, KubeFSLevel::DataKey) {
+                    return Err(std::io::Error::from_raw_os_error(libc::EPERM).into());
+                }
+
+                self.set_key(&inode, name, None)?;
+                self.table().remove_inode(&entry.ino);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Finds the pod, container and path of an entry in the `fs` directory
+    /// of a pod.
+    fn container_path(&self, inode: &KubeFSInode) -> anyhow::Result<ContainerPath> {
+        let table = self.table();
+        let mut names = vec![];
+        let mut entry = inode.clone();
+
+        while !matches!(entry.level, KubeFSLevel::ContainerFsRoot) {
+            names.push(entry.name.clone());
+            entry = table
+                .get_inode(&entry.parent.ok_or(KubeFSInodeError::MissingInode)?)
+                .ok_or(KubeFSInodeError::MissingInode)?
+                .clone();
+        }
+        names.reverse();
+
+        let (pod, _, namespace) = table.get_object_of(&entry)?;
+
+        Ok(ContainerPath {
+            pod: pod.name,
+            namespace: namespace.ok_or(KubeFSInodeError::MissingInode)?,
+            container: entry.name,
+            path: format!("/{}", names.join("/")),
+        })
+    }
+
+    /// Runs a command in a container and returns its stdout, failing with
+    /// its stderr when it exits with an error.
+    fn run_in_container(
+        &self,
+        ino: &u64,
+        target: &ContainerPath,
+        command: &[&str],
+        stdin: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let output = self.client(ino)?.exec(
+            &target.pod,
+            &target.namespace,
+            &target.container,
+            command,
+            stdin,
+        )?;
+
+        if !output.success {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(KubeFSInodeError::ContainerCommand(message).into());
+        }
+
+        Ok(output.stdout)
+    }
+
+    fn upload(
+        &self,
+        ino: &u64,
+        path: &str,
+        target: &ContainerPath,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.run_in_container(ino, target, &["sh", "-c", "cat > \"$1\"", "sh", path], data)?;
+
+        Ok(())
+    }
+
+    /// Starts following the log of the current instance of a container.
+    /// Returns `false` for the logs of previous instances, which are
+    /// complete.
+    pub fn follow_log(&self, ino: &u64, log: &Arc<FollowedLog>) -> anyhow::Result<bool> {
+        let inode = self.get_inode(ino).ok_or(KubeFSInodeError::MissingInode)?;
+        let (container, previous) = pod::parse_log_file(&inode.name);
+
+        if !matches!(inode.level, KubeFSLevel::Log) || previous {
+            return Ok(false);
+        }
+
+        let (pod, _, namespace) = self.table().get_object_of(&inode)?;
+        self.client(ino)?.follow_logs(
+            &pod.name,
+            &namespace.ok_or(KubeFSInodeError::MissingInode)?,
+            container,
+            log.clone(),
+        )?;
+
+        Ok(true)
+    }
+
+    /// Appends a failure to the error log file, dropping the oldest entries
+    /// once the log is full.
+    pub fn record_error(&self, ino: &u64, error: &anyhow::Error) {
+        let mut table = self.table();
+        let entry = format!(
+            "{} {}: {:#}\n",
+            time::now_utc().rfc3339(),
+            table.get_path(ino),
+            error
+        );
+
+        if table.errors.len() == MAX_ERROR_LOG_ENTRIES {
+            table.errors.pop_front();
+        }
+        table.errors.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_find_inode_by_parent_root() {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.table().inodes.insert(
+            2,
+            KubeFSInode {
+                ino: 2,
+                parent: Some(1),
+                name: String::from("default"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        inodes.table().inodes.insert(
+            3,
+            KubeFSInode {
+                ino: 3,
+                parent: Some(1),
+                name: String::from("dev"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        inodes.table().inodes.insert(
+            4,
+            KubeFSInode {
+                ino: 4,
+                parent: Some(2),
+                name: String::from("prod"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        let child_inodes = inodes.find_inode_by_parent(&1);
+
+        assert_eq!(child_inodes.len(), 2);
+    }
+
+    #[test]
+    fn test_find_inode_by_parent_when_parent_does_not_exist() {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        let child_inodes = inodes.find_inode_by_parent(&2);
+
+        assert_eq!(child_inodes.len(), 0);
+    }
+
+    #[test]
+    fn test_lookup_inode_by_parent_and_name() {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.table().inodes.insert(
+            2,
+            KubeFSInode {
+                ino: 2,
+                parent: Some(1),
+                name: String::from("default"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        inodes.table().inodes.insert(
+            3,
+            KubeFSInode {
+                ino: 3,
+                parent: Some(1),
+                name: String::from("dev"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        let inode = inodes.lookup_inode_by_parent_and_name(&1, "dev");
+
+        assert!(inode.is_some());
+
+        if let Some(n) = inode {
+            assert_eq!(n.ino, 3);
+        }
+    }
+
+    #[test]
+    fn test_lookup_inode_by_parent_when_no_node_exists() {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+        let inode = inodes.lookup_inode_by_parent_and_name(&1, "dev");
+
+        assert!(inode.is_none());
+    }
+
+    #[test]
+    fn test_delete_by_parent_ino() {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+        inodes.table().inodes.insert(
+            2,
+            KubeFSInode {
+                ino: 2,
+                parent: Some(1),
+                name: String::from("default"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        inodes.table().inodes.insert(
+            3,
+            KubeFSInode {
+                ino: 3,
+                parent: Some(1),
+                name: String::from("dev"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        inodes.table().inodes.insert(
+            4,
+            KubeFSInode {
+                ino: 4,
+                parent: None,
+                name: String::from("dev"),
+                level: KubeFSLevel::Namespace,
+            },
+        );
+
+        assert_eq!(inodes.table().inodes.len(), 4);
+
+        inodes.table().delete_by_parent_ino(&1);
+        assert_eq!(inodes.table().inodes.len(), 2);
+    }
+
+    #[test]
+    fn test_fetch_child_nodes_for_node_when_root() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        let root_node = inodes.table().inodes[&1].clone();
+
+        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+        assert_eq!(inodes.table().inodes.len(), 6);
+        println!("{:?}", inodes.table().inodes);
+        assert_eq!(inodes.table().inodes.get(&2).unwrap().name, "default");
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&1, CLUSTER_DIRECTORY)
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_child_nodes_for_node_when_namespace() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        let root_node = inodes.table().inodes[&1].clone();
+
+        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+
+        let default_namespace_node = inodes.table().inodes[&2].clone();
+
+        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+
+        assert_eq!(inodes.table().inodes.len(), 6 + MOCK_OBJECT_KINDS.len());
+        for kind in MOCK_OBJECT_KINDS.iter() {
+            child(&inodes, default_namespace_node.ino, kind);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_child_nodes_for_node_when_object() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        let root_node = inodes.table().inodes[&1].clone();
+
+        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+
+        let default_namespace_node = inodes.table().inodes[&2].clone();
+
+        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+
+        let deployments_node = child(&inodes, default_namespace_node.ino, "deployments");
+        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+
+        assert_eq!(inodes.table().inodes.len(), 9 + MOCK_OBJECT_KINDS.len());
+        assert!(matches!(
+            child(&inodes, deployments_node.ino, "deploy-1").level,
+            KubeFSLevel::File
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_yaml_for_file() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        let root_node = inodes.table().inodes[&1].clone();
+
+        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+
+        let default_namespace_node = inodes.table().inodes[&2].clone();
+
+        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+
+        let deployments_node = child(&inodes, default_namespace_node.ino, "deployments");
+        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+
+        let deploy_1_node = child(&inodes, deployments_node.ino, "deploy-1");
+
+        let contents = inodes.get_file_contents(&deploy_1_node.ino)?;
+
+        assert_eq!(contents, "Data");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_yaml_for_cluster_scoped_file() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        let cluster_node = child(&inodes, 1, CLUSTER_DIRECTORY);
+        inodes.fetch_child_nodes_for_node(&cluster_node.ino)?;
+
+        let nodes_node = child(&inodes, cluster_node.ino, "nodes");
+        inodes.fetch_child_nodes_for_node(&nodes_node.ino)?;
+
+        let node_1_node = child(&inodes, nodes_node.ino, "node-1");
+
+        assert_eq!(inodes.get_file_contents(&node_1_node.ino)?, "Node");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inodes_are_unique_across_namespaces() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+
+        let default_node = child(&inodes, 1, "default");
+        let dev_node = child(&inodes, 1, "dev");
+        inodes.fetch_child_nodes_for_node(&default_node.ino)?;
+        inodes.fetch_child_nodes_for_node(&dev_node.ino)?;
+
+        let default_pods = child(&inodes, default_node.ino, "pods");
+        let dev_pods = child(&inodes, dev_node.ino, "pods");
+
+        assert_ne!(default_pods.ino, dev_pods.ino);
+        assert_eq!(
+            inodes.get_inode(&default_pods.ino).unwrap().parent,
+            Some(default_node.ino)
+        );
+        assert_eq!(
+            inodes.get_inode(&dev_pods.ino).unwrap().parent,
+            Some(dev_node.ino)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inodes_are_stable_across_fetches() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        let default_node = child(&inodes, 1, "default");
+        inodes.fetch_child_nodes_for_node(&default_node.ino)?;
+        let deployments_node = child(&inodes, default_node.ino, "deployments");
+        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+        let deploy_1_node = child(&inodes, deployments_node.ino, "deploy-1");
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&default_node.ino)?;
+        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+
+        assert_eq!(child(&inodes, 1, "default").ino, default_node.ino);
+        assert_eq!(
+            child(&inodes, deployments_node.ino, "deploy-1").ino,
+            deploy_1_node.ino
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forget_releases_inode() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        let default_node = child(&inodes, 1, "default");
+        inodes.fetch_child_nodes_for_node(&default_node.ino)?;
+
+        inodes.increment_lookup(&default_node.ino);
+        inodes.increment_lookup(&default_node.ino);
+
+        inodes.forget(&default_node.ino, 1);
+        assert!(inodes.get_inode(&default_node.ino).is_some());
 
-                let namespace = self
-                    .get_inode(&object.parent.ok_or(KubeFSInodeError::MissingInode)?)
-                    .ok_or(KubeFSInodeError::MissingInode)?
-                    .clone();
+        inodes.forget(&default_node.ino, 1);
+        assert!(inodes.get_inode(&default_node.ino).is_none());
+        assert!(inodes.find_inode_by_parent(&default_node.ino).is_empty());
 
-                self.client
-                    .update_object(&inode.name, &namespace.name, &object.name, data)?;
-            }
-            _ => {}
-        }
+        inodes.forget(&1, 1);
+        assert!(inodes.get_inode(&1).is_some());
 
         Ok(())
     }
 
-    pub fn delete_object(&mut self, name: &str, parent_ino: &u64) -> anyhow::Result<()> {
-        let inode = self
-            .get_inode(&parent_ino)
-            .ok_or(KubeFSInodeError::MissingInode)?
-            .clone();
+    #[test]
+    fn test_record_error_is_readable_from_error_log() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        match inode.level {
-            KubeFSLevel::Root => {
-                self.client.remove_namespace(name)?;
-            }
-            _ => {}
-        };
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let deployments_node = child(&inodes, 2, "deployments");
+        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+        let deploy_1_node = child(&inodes, deployments_node.ino, "deploy-1");
+
+        inodes.record_error(&deploy_1_node.ino, &anyhow::anyhow!("admission denied"));
+
+        let error_log = child(&inodes, 1, ERROR_LOG_FILE);
+        let contents = inodes.get_file_contents(&error_log.ino)?;
+
+        assert!(contents.contains("/default/deployments/deploy-1: admission denied"));
 
         Ok(())
     }
 
-    fn delete_by_parent_ino(&mut self, parent: &u64) {
-        self.inodes.retain(|_, inode| inode.parent != Some(*parent))
-    }
-}
+    #[test]
+    fn test_create_object_creates_namespace() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let inodes = KubeFSINodes::new(Box::new(client));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        inodes.create_object("test", &1, &Vec::new())?;
+
+        Ok(())
+    }
 
     #[test]
-    fn test_find_inode_by_parent_root() {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_create_object_creates_object_in_kind_directory() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        inodes.inodes.insert(
-            2,
-            KubeFSInode {
-                ino: 2,
-                parent: Some(1),
-                name: String::from("default"),
-                level: KubeFSLevel::Namespace,
-            },
-        );
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let deployments_node = child(&inodes, 2, "deployments");
 
-        inodes.inodes.insert(
-            3,
-            KubeFSInode {
-                ino: 3,
-                parent: Some(1),
-                name: String::from("dev"),
-                level: KubeFSLevel::Namespace,
-            },
-        );
+        let created = inodes.create_object("new.yaml", &deployments_node.ino, b"name: new-app")?;
 
-        inodes.inodes.insert(
-            4,
-            KubeFSInode {
-                ino: 4,
-                parent: Some(2),
-                name: String::from("prod"),
-                level: KubeFSLevel::Namespace,
-            },
+        assert_eq!(created.name, "new-app");
+        assert_eq!(
+            child(&inodes, deployments_node.ino, "new-app").ino,
+            created.ino
         );
+        assert!(inodes
+            .create_object("new.yaml", &child(&inodes, 2, "pods").ino, b"name: new-app")
+            .is_err());
 
-        let child_inodes = inodes.find_inode_by_parent(&1);
+        Ok(())
+    }
 
-        assert_eq!(child_inodes.len(), 2);
+    #[test]
+    fn test_contexts_are_top_level_directories() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::with_contexts(vec![
+            (String::from("prod"), Box::new(MockClient::new())),
+            (String::from("staging"), Box::new(MockClient::new())),
+        ]);
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        assert_eq!(inodes.find_inode_by_parent(&1).len(), 3);
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&1, "default")
+            .is_none());
+        assert!(inodes.create_object("test", &1, &[]).is_err());
+
+        let prod = child(&inodes, 1, "prod");
+        let staging = child(&inodes, 1, "staging");
+        inodes.fetch_child_nodes_for_node(&prod.ino)?;
+        inodes.fetch_child_nodes_for_node(&staging.ino)?;
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&staging.ino, CLUSTER_DIRECTORY)
+            .is_some());
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&staging.ino, ERROR_LOG_FILE)
+            .is_none());
+
+        let prod_default = child(&inodes, prod.ino, "default");
+        let staging_default = child(&inodes, staging.ino, "default");
+        assert_ne!(prod_default.ino, staging_default.ino);
+
+        inodes.fetch_child_nodes_for_node(&staging_default.ino)?;
+        let deployments = child(&inodes, staging_default.ino, "deployments");
+        inodes.fetch_child_nodes_for_node(&deployments.ino)?;
+        let deploy_1 = child(&inodes, deployments.ino, "deploy-1");
+        assert_eq!(inodes.get_file_contents(&deploy_1.ino)?, "Data");
+
+        inodes.create_object("test", &staging.ino, &[])?;
+        inodes.record_error(&deploy_1.ino, &anyhow::anyhow!("admission denied"));
+        let error_log = child(&inodes, 1, ERROR_LOG_FILE);
+        assert!(inodes
+            .get_file_contents(&error_log.ino)?
+            .contains("/staging/default/deployments/deploy-1: admission denied"));
+
+        Ok(())
     }
 
     #[test]
-    fn test_find_inode_by_parent_when_parent_does_not_exist() {
-        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_namespace_filter_patterns() {
+        let filter = NamespaceFilter {
+            include: vec![String::from("team-*"), String::from("default")],
+            exclude: vec![String::from("*-canary")],
+        };
 
-        let child_inodes = inodes.find_inode_by_parent(&2);
+        assert!(filter.allows("default"));
+        assert!(filter.allows("team-a"));
+        assert!(filter.allows("team-"));
+        assert!(!filter.allows("team-a-canary"));
+        assert!(!filter.allows("kube-system"));
+        assert!(!filter.allows("défault"));
+        assert!(filter.literal_namespaces().is_none());
+        assert!(NamespaceFilter::default().allows("kube-system"));
+    }
 
-        assert_eq!(child_inodes.len(), 0);
+    #[test]
+    fn test_namespace_filter_limits_namespaces() -> Result<(), anyhow::Error> {
+        let inodes =
+            KubeFSINodes::new(Box::new(MockClient::new())).with_namespace_filter(NamespaceFilter {
+                include: vec![],
+                exclude: vec![String::from("d*")],
+            });
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        assert!(inodes.lookup_inode_by_parent_and_name(&1, "prod").is_some());
+        assert!(inodes.lookup_inode_by_parent_and_name(&1, "dev").is_none());
+        assert!(inodes.create_object("dev-2", &1, &[]).is_err());
+        assert!(inodes.delete_object("default", &1).is_err());
+
+        // Exact names are shown without listing, even ones which don't exist
+        let inodes =
+            KubeFSINodes::new(Box::new(MockClient::new())).with_namespace_filter(NamespaceFilter {
+                include: vec![String::from("dev"), String::from("qa")],
+                exclude: vec![],
+            });
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        assert!(inodes.lookup_inode_by_parent_and_name(&1, "qa").is_some());
+        assert!(inodes.lookup_inode_by_parent_and_name(&1, "prod").is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn test_lookup_inode_by_parent_and_name() {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_pods_are_directories_with_logs() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        inodes.inodes.insert(
-            2,
-            KubeFSInode {
-                ino: 2,
-                parent: Some(1),
-                name: String::from("default"),
-                level: KubeFSLevel::Namespace,
-            },
-        );
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let pods = child(&inodes, 2, "pods").ino;
+        inodes.fetch_child_nodes_for_node(&pods)?;
 
-        inodes.inodes.insert(
-            3,
-            KubeFSInode {
-                ino: 3,
-                parent: Some(1),
-                name: String::from("dev"),
-                level: KubeFSLevel::Namespace,
-            },
+        let pod = child(&inodes, pods, "web-1");
+        assert!(matches!(pod.level, KubeFSLevel::ObjectDirectory));
+        inodes.fetch_child_nodes_for_node(&pod.ino)?;
+
+        let manifest = child(&inodes, pod.ino, MANIFEST_FILE).ino;
+        inodes.update_object(&manifest, "Updated", None)?;
+        assert_eq!(inodes.get_stat(&manifest).unwrap().size, 7);
+
+        let logs = child(&inodes, pod.ino, LOG_DIRECTORY).ino;
+        inodes.fetch_child_nodes_for_node(&logs)?;
+        let current = child(&inodes, logs, "web").ino;
+        let previous = child(&inodes, logs, "web.previous").ino;
+
+        assert_eq!(
+            inodes.get_file_contents(&previous)?,
+            "default/web-1/web previous=true\n"
         );
 
-        let inode = inodes.lookup_inode_by_parent_and_name(&1, "dev");
+        let log = Arc::new(FollowedLog::new());
+        assert!(!inodes.follow_log(&previous, &log)?);
+        assert!(inodes.follow_log(&current, &log)?);
+        assert_eq!(log.read(|data| data.to_vec()), b"started\n");
 
-        assert_ne!(true, inode.is_none());
+        let containers = child(&inodes, pod.ino, CONTAINERS_DIRECTORY).ino;
+        inodes.fetch_child_nodes_for_node(&containers)?;
+        let web = child(&inodes, containers, "web").ino;
+        inodes.fetch_child_nodes_for_node(&web)?;
 
-        if let Some(n) = inode {
-            assert_eq!(n.ino, 3);
-        }
+        assert_eq!(
+            inodes.get_file_contents(&child(&inodes, web, "image").ino)?,
+            "nginx\n"
+        );
+        assert_eq!(
+            inodes.get_file_contents(&child(&inodes, web, "restartCount").ino)?,
+            "1\n"
+        );
+
+        let output = child(&inodes, web, EXEC_OUTPUT_FILE).ino;
+        inodes.update_object(&child(&inodes, web, EXEC_FILE).ino, "", None)?;
+        assert_eq!(inodes.get_file_contents(&output)?, "");
+        inodes.update_object(&child(&inodes, web, EXEC_FILE).ino, "ps aux\n", None)?;
+        assert_eq!(
+            inodes.get_file_contents(&output)?,
+            "default/web-1/web ran ps aux\n"
+        );
+
+        Ok(())
     }
 
     #[test]
-    fn test_lookup_inode_by_parent_when_no_node_exists() {
-        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
-        let inode = inodes.lookup_inode_by_parent_and_name(&1, "dev");
+    fn test_container_filesystem_through_exec() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let uploads = client.uploads.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let pods = child(&inodes, 2, "pods").ino;
+        inodes.fetch_child_nodes_for_node(&pods)?;
+        let pod = child(&inodes, pods, "web-1").ino;
+        inodes.fetch_child_nodes_for_node(&pod)?;
+        let fs = child(&inodes, pod, CONTAINER_FS_DIRECTORY).ino;
+        inodes.fetch_child_nodes_for_node(&fs)?;
+
+        let root = child(&inodes, fs, "web");
+        assert!(matches!(root.level, KubeFSLevel::ContainerFsRoot));
+        inodes.fetch_child_nodes_for_node(&root.ino)?;
+
+        let etc = child(&inodes, root.ino, "etc");
+        assert!(matches!(etc.level, KubeFSLevel::ContainerFsDir));
+        inodes.fetch_child_nodes_for_node(&etc.ino)?;
+
+        let hosts = child(&inodes, etc.ino, "hosts").ino;
+        assert_eq!(inodes.get_file_bytes(&hosts)?, b"contents of /etc/hosts\n");
+
+        inodes.write_file(&hosts, b"127.0.0.1 localhost\n".to_vec(), None)?;
+        let created = inodes.create_file("resolv.conf", &etc.ino)?;
+        assert!(matches!(created.level, KubeFSLevel::ContainerFsFile));
+        assert_eq!(
+            *uploads.lock().unwrap(),
+            vec![
+                (
+                    String::from("/etc/hosts"),
+                    b"127.0.0.1 localhost\n".to_vec()
+                ),
+                (String::from("/etc/resolv.conf"), vec![]),
+            ]
+        );
 
-        assert_eq!(true, inode.is_none());
+        let err = inodes.delete_object("gone", &root.ino).unwrap_err();
+        assert!(err.to_string().contains("No such file or directory"));
+        inodes.delete_object("hostname", &root.ino)?;
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&root.ino, "hostname")
+            .is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn test_delete_by_parent_ino() {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
-        inodes.inodes.insert(
-            2,
-            KubeFSInode {
-                ino: 2,
-                parent: Some(1),
-                name: String::from("default"),
-                level: KubeFSLevel::Namespace,
-            },
-        );
+    fn test_secret_keys_are_files() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let patches = client.patches.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let secrets = child(&inodes, 2, "secrets").ino;
+        inodes.fetch_child_nodes_for_node(&secrets)?;
+        let secret = child(&inodes, secrets, "db-creds");
+        assert!(matches!(secret.level, KubeFSLevel::ObjectDirectory));
+        inodes.fetch_child_nodes_for_node(&secret.ino)?;
+
+        // The key named like the manifest is shadowed by it
+        let names: Vec<String> = inodes
+            .find_inode_by_parent(&secret.ino)
+            .into_iter()
+            .map(|inode| inode.name)
+            .collect();
+        assert_eq!(names.len(), 3);
+
+        let password = child(&inodes, secret.ino, "password").ino;
+        assert_eq!(inodes.get_file_bytes(&password)?, b"hunter2");
+        assert_eq!(inodes.get_stat(&password).unwrap().size, 7);
+
+        inodes.write_file(&password, b"swordfish".to_vec(), None)?;
+        let user = inodes.create_file("user", &secret.ino)?;
+        assert!(matches!(user.level, KubeFSLevel::DataKey));
+        inodes.delete_object("password", &secret.ino)?;
+        assert!(inodes.delete_object(MANIFEST_FILE, &secret.ino).is_err());
 
-        inodes.inodes.insert(
-            3,
-            KubeFSInode {
-                ino: 3,
-                parent: Some(1),
-                name: String::from("dev"),
-                level: KubeFSLevel::Namespace,
-            },
+        assert_eq!(
+            *patches.lock().unwrap(),
+            vec![
+                serde_json::json!({ "data": { "password": "c3dvcmRmaXNo" } }),
+                serde_json::json!({ "data": { "user": "" } }),
+                serde_json::json!({ "data": { "password": null } }),
+            ]
         );
 
-        inodes.inodes.insert(
-            4,
-            KubeFSInode {
-                ino: 4,
-                parent: None,
-                name: String::from("dev"),
-                level: KubeFSLevel::Namespace,
-            },
-        );
+        Ok(())
+    }
 
-        assert_eq!(inodes.inodes.len(), 4);
+    #[test]
+    fn test_create_file_in_pod_directory_is_rejected() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let patches = client.patches.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let pods = child(&inodes, 2, "pods").ino;
+        inodes.fetch_child_nodes_for_node(&pods)?;
+        let pod = child(&inodes, pods, "web-1");
+        assert!(matches!(pod.level, KubeFSLevel::ObjectDirectory));
+
+        let err = inodes.create_file("foo", &pod.ino).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>()
+                .and_then(|e| e.raw_os_error()),
+            Some(libc::EPERM)
+        );
+        assert!(inodes
+            .lookup_inode_by_parent_and_name(&pod.ino, "foo")
+            .is_none());
+        assert!(patches.lock().unwrap().is_empty());
 
-        inodes.delete_by_parent_ino(&1);
-        assert_eq!(inodes.inodes.len(), 2);
+        Ok(())
     }
 
+    const MOCK_OBJECT_KINDS: [&str; 5] =
+        ["configmaps", "deployments", "pods", "secrets", "services"];
+
     #[test]
-    fn test_fetch_child_nodes_for_node_when_root() -> Result<(), anyhow::Error> {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_delete_object_deletes_from_kind_directory() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        let root_node = inodes.inodes[&1].clone();
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
 
-        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
-        assert_eq!(inodes.inodes.len(), 4);
-        println!("{:?}", inodes.inodes);
-        assert_eq!(inodes.inodes.get(&2).unwrap().name, "default");
+        inodes.delete_object("deploy-1", &child(&inodes, 2, "deployments").ino)?;
+        assert!(inodes
+            .delete_object("deploy-1", &child(&inodes, 2, "pods").ino)
+            .is_err());
 
         Ok(())
     }
 
     #[test]
-    fn test_fetch_child_nodes_for_node_when_namespace() -> Result<(), anyhow::Error> {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
-
-        let root_node = inodes.inodes[&1].clone();
+    fn test_saving_redacted_key_keeps_value() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let patches = client.patches.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
 
-        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let secrets = child(&inodes, 2, "secrets").ino;
+        inodes.fetch_child_nodes_for_node(&secrets)?;
+        let secret = child(&inodes, secrets, "db-creds").ino;
+        inodes.fetch_child_nodes_for_node(&secret)?;
 
-        let default_namespace_node = inodes.inodes[&2].clone();
+        // The mock serves token redacted
+        let token = child(&inodes, secret, "token").ino;
+        let shown = inodes.get_file_bytes(&token)?;
+        assert_eq!(shown, data::REDACTED.as_bytes());
 
-        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+        inodes.write_file(&token, shown, None)?;
+        assert!(patches.lock().unwrap().is_empty());
 
-        assert_eq!(inodes.inodes.len(), 4 + KUBEFS_OBJECTS.len());
+        inodes.write_file(&token, b"rotated".to_vec(), None)?;
         assert_eq!(
-            inodes.inodes.get(&MAX_SUPPORTED_NAMESPACES).unwrap().name,
-            KUBEFS_OBJECTS[0]
+            *patches.lock().unwrap(),
+            vec![serde_json::json!({ "data": { "token": "cm90YXRlZA==" } })]
         );
 
         Ok(())
     }
 
     #[test]
-    fn test_fetch_child_nodes_for_node_when_object() -> Result<(), anyhow::Error> {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_rmdir_deletes_pods() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let deletes = client.deletes.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let pods = child(&inodes, 2, "pods").ino;
+        inodes.fetch_child_nodes_for_node(&pods)?;
+        assert!(matches!(
+            child(&inodes, pods, "web-1").level,
+            KubeFSLevel::ObjectDirectory
+        ));
+
+        // rmdir on a pod directory, which holds files, deletes the pod
+        inodes.delete_object("web-1", &pods)?;
+        assert_eq!(deletes.lock().unwrap()[0].0, "web-1");
 
-        let root_node = inodes.inodes[&1].clone();
+        Ok(())
+    }
 
-        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+    #[test]
+    fn test_rmdir_deletes_config_maps_and_secrets() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let deletes = client.deletes.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+
+        for (kind, name) in &[("configmaps", "settings"), ("secrets", "db-creds")] {
+            let kind = child(&inodes, 2, kind).ino;
+            inodes.fetch_child_nodes_for_node(&kind)?;
+            let object = child(&inodes, kind, name);
+            assert!(matches!(object.level, KubeFSLevel::ObjectDirectory));
+            inodes.fetch_child_nodes_for_node(&object.ino)?;
+
+            // The manifest is not a key, so it can only go with the object
+            assert!(inodes.delete_object(MANIFEST_FILE, &object.ino).is_err());
+            inodes.delete_object(name, &kind)?;
+        }
 
-        let default_namespace_node = inodes.inodes[&2].clone();
+        let deleted: Vec<String> = deletes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        assert_eq!(deleted, vec!["settings", "db-creds"]);
 
-        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+        Ok(())
+    }
 
-        let deployments_node = inodes.inodes[&MAX_SUPPORTED_NAMESPACES].clone();
-        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+    #[test]
+    fn test_delete_object_passes_delete_options() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let deletes = client.deletes.clone();
+        let inodes = KubeFSINodes::new(Box::new(client)).with_delete_options(DeleteOptions {
+            propagation_policy: Some(String::from("Foreground")),
+            grace_period_seconds: Some(0),
+        });
+
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+
+        inodes.delete_object("deploy-1", &child(&inodes, 2, "deployments").ino)?;
 
-        assert_eq!(inodes.inodes.len(), 7 + KUBEFS_OBJECTS.len());
         assert_eq!(
-            inodes
-                .inodes
-                .get(&(MAX_SUPPORTED_NAMESPACES + KUBEFS_OBJECTS.len() as u64))
-                .unwrap()
-                .name,
-            "deploy-1"
+            *deletes.lock().unwrap(),
+            vec![(
+                String::from("deploy-1"),
+                DeleteOptions {
+                    propagation_policy: Some(String::from("Foreground")),
+                    grace_period_seconds: Some(0),
+                }
+            )]
         );
 
         Ok(())
     }
 
     #[test]
-    fn test_get_yaml_for_file() -> Result<(), anyhow::Error> {
-        let mut inodes = KubeFSINodes::new(Box::new(MockClient::new()));
+    fn test_object_stat_from_listing() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        let root_node = inodes.inodes[&1].clone();
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let deployments = child(&inodes, 2, "deployments").ino;
+        inodes.fetch_child_nodes_for_node(&deployments)?;
 
-        inodes.fetch_child_nodes_for_node(&root_node.ino)?;
+        let deploy_1 = child(&inodes, deployments, "deploy-1").ino;
+        let stat = inodes.get_stat(&deploy_1).unwrap();
+        assert_eq!(stat.size, 100);
+        assert_eq!(stat.created, Some(1000));
+        assert_eq!(stat.modified, Some(2000));
 
-        let default_namespace_node = inodes.inodes[&2].clone();
+        // Reading renders the manifest again, which gives the exact size
+        inodes.get_file_contents(&deploy_1)?;
+        assert_eq!(inodes.get_stat(&deploy_1).unwrap().size, 4);
 
-        inodes.fetch_child_nodes_for_node(&default_namespace_node.ino)?;
+        inodes.update_object(&deploy_1, "Updated", None)?;
+        assert_eq!(inodes.get_stat(&deploy_1).unwrap().size, 7);
+        assert_eq!(inodes.get_stat(&deploy_1).unwrap().modified, Some(3000));
 
-        let deployments_node = inodes.inodes[&MAX_SUPPORTED_NAMESPACES].clone();
-        inodes.fetch_child_nodes_for_node(&deployments_node.ino)?;
+        Ok(())
+    }
 
-        let deploy_1_node = inodes
-            .inodes
-            .get(&(MAX_SUPPORTED_NAMESPACES + KUBEFS_OBJECTS.len() as u64))
-            .ok_or(KubeFSInodeError::MissingInode)?
-            .clone();
+    #[test]
+    fn test_write_file_is_based_on_version_read() -> Result<(), anyhow::Error> {
+        let client = MockClient::new();
+        let updates = client.updates.clone();
+        let inodes = KubeFSINodes::new(Box::new(client));
 
-        let contents = inodes.get_file_contents(&deploy_1_node.ino)?;
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let deployments = child(&inodes, 2, "deployments").ino;
+        inodes.fetch_child_nodes_for_node(&deployments)?;
+        let deploy_1 = child(&inodes, deployments, "deploy-1").ino;
 
-        assert_eq!(contents, "Data");
+        let (data, read_at) = inodes.read_file(&deploy_1)?;
+        assert_eq!(data, b"Data");
+
+        // The object changes on the server before the edit is written
+        inodes.fetch_child_nodes_for_node(&deployments)?;
+        assert_ne!(inodes.read_file(&deploy_1)?.1, read_at);
+
+        let written = inodes.write_file(&deploy_1, b"Updated".to_vec(), read_at.as_deref())?;
+        assert_eq!(written.as_deref(), Some("updated"));
+        assert_eq!(*updates.lock().unwrap(), vec![read_at]);
 
         Ok(())
     }
 
     #[test]
-    fn test_create_object_creates_namespace() -> Result<(), anyhow::Error> {
-        let client = MockClient::new();
-        let mut inodes = KubeFSINodes::new(Box::new(client));
+    fn test_object_modified_on_resource_version_change() -> Result<(), anyhow::Error> {
+        let inodes = KubeFSINodes::new(Box::new(MockClient::new()));
 
-        inodes.create_object("test", &1, &Vec::new())?;
+        inodes.fetch_child_nodes_for_node(&1)?;
+        inodes.fetch_child_nodes_for_node(&2)?;
+        let deployments = child(&inodes, 2, "deployments").ino;
+
+        inodes.fetch_child_nodes_for_node(&deployments)?;
+        let deploy_2 = child(&inodes, deployments, "deploy-2").ino;
+        assert_eq!(inodes.get_stat(&deploy_2).unwrap().modified, Some(1000));
+
+        inodes.fetch_child_nodes_for_node(&deployments)?;
+        assert!(inodes.get_stat(&deploy_2).unwrap().modified > Some(1000));
 
         Ok(())
     }
 
-    struct MockClient {}
+    fn child(inodes: &KubeFSINodes, parent: u64, name: &str) -> KubeFSInode {
+        inodes
+            .lookup_inode_by_parent_and_name(&parent, name)
+            .expect("child inode")
+    }
+
+    /// Paths written in containers, with what was written to them
+    type Uploads = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    struct MockClient {
+        lists: AtomicU64,
+        uploads: Uploads,
+        patches: Arc<Mutex<Vec<Value>>>,
+        deletes: Arc<Mutex<Vec<(String, DeleteOptions)>>>,
+        /// resourceVersion each update was based on
+        updates: Arc<Mutex<Vec<Option<String>>>>,
+    }
 
     impl MockClient {
         pub fn new() -> Self {
-            MockClient {}
+            MockClient {
+                lists: AtomicU64::new(0),
+                uploads: Arc::new(Mutex::new(vec![])),
+                patches: Arc::new(Mutex::new(vec![])),
+                deletes: Arc::new(Mutex::new(vec![])),
+                updates: Arc::new(Mutex::new(vec![])),
+            }
+        }
+    }
+
+    fn mock_object_names(namespace: Option<&str>, object_name: &str) -> Vec<&'static str> {
+        if namespace == Some("default") && object_name == "deployments" {
+            vec!["deploy-1", "deploy-2", "deploy-3"]
+        } else if namespace.is_none() && object_name == "nodes" {
+            vec!["node-1"]
+        } else if namespace == Some("default") && object_name == "pods" {
+            vec!["web-1"]
+        } else if namespace == Some("default") && object_name == "secrets" {
+            vec!["db-creds"]
+        } else if namespace == Some("default") && object_name == "configmaps" {
+            vec!["settings"]
+        } else {
+            vec![]
         }
     }
 
     impl K8sInteractions for MockClient {
-        fn get_namespaces(&mut self) -> Result<Vec<String>, anyhow::Error> {
-            return Ok(vec![
+        fn get_namespaces(&self) -> Result<Vec<String>, anyhow::Error> {
+            Ok(vec![
                 String::from("default"),
                 String::from("dev"),
                 String::from("prod"),
-            ]);
+            ])
+        }
+
+        fn get_object_kinds(&self, namespaced: bool) -> Result<Vec<String>, anyhow::Error> {
+            if namespaced {
+                Ok(MOCK_OBJECT_KINDS.iter().map(|k| k.to_string()).collect())
+            } else {
+                Ok(vec![String::from("nodes")])
+            }
         }
 
         fn get_objects(
-            &mut self,
-            namespace: &str,
+            &self,
+            namespace: Option<&str>,
             object_name: &str,
-        ) -> Result<Vec<String>, anyhow::Error> {
-            if namespace == "default" && object_name == "deployments" {
-                Ok(vec![
-                    String::from("deploy-1"),
-                    String::from("deploy-2"),
-                    String::from("deploy-3"),
-                ])
-            } else {
-                Ok(vec![])
+        ) -> Result<Vec<KubeObjectEntry>, anyhow::Error> {
+            let names = mock_object_names(namespace, object_name);
+            let lists = self.lists.fetch_add(1, Ordering::SeqCst) + 1;
+
+            // Only deploy-1 has managedFields, the others change their
+            // resourceVersion on every list
+            Ok(names
+                .into_iter()
+                .map(|name| KubeObjectEntry {
+                    name: name.to_string(),
+                    uid: Some(format!("{}-uid", name)),
+                    stat: ObjectStat {
+                        size: 100,
+                        created: Some(1000),
+                        modified: if name == "deploy-1" { Some(2000) } else { None },
+                        resource_version: Some(lists.to_string()),
+                    },
+                })
+                .collect())
+        }
+
+        fn get_object(
+            &self,
+            name: &str,
+            _namespace: Option<&str>,
+            object_name: &str,
+        ) -> anyhow::Result<Value> {
+            if object_name == "secrets" {
+                return Ok(serde_json::json!({
+                    "metadata": { "name": name },
+                    "data": {
+                        "password": "aHVudGVyMg==",
+                        "manifest": "eA==",
+                        "token": data::REDACTED
+                    }
+                }));
             }
+
+            Ok(serde_json::json!({
+                "metadata": { "name": name },
+                "spec": { "containers": [{ "name": "web", "image": "nginx" }] },
+                "status": {
+                    "containerStatuses": [{
+                        "name": "web",
+                        "restartCount": 1,
+                        "lastState": { "terminated": { "exitCode": 1 } }
+                    }]
+                }
+            }))
         }
 
         fn get_object_data_as_yaml(
-            &mut self,
+            &self,
             name: &str,
-            namespace: &str,
+            namespace: Option<&str>,
             object_name: &str,
-        ) -> anyhow::Result<String> {
-            if name == "deploy-1" && namespace == "default" && object_name == "deployments" {
-                Ok(String::from("Data"))
+        ) -> anyhow::Result<(String, Option<String>)> {
+            // Objects change with every list, like in get_objects
+            let resource_version = Some(self.lists.load(Ordering::SeqCst).to_string());
+
+            if name == "deploy-1" && namespace == Some("default") && object_name == "deployments" {
+                Ok((String::from("Data"), resource_version))
+            } else if name == "node-1" && namespace.is_none() && object_name == "nodes" {
+                Ok((String::from("Node"), resource_version))
             } else {
-                Ok(String::new())
+                Ok((String::new(), resource_version))
             }
         }
 
-        fn create_namespace(&mut self, _name: &str) -> anyhow::Result<()> {
+        fn create_namespace(&self, _name: &str) -> anyhow::Result<()> {
             Ok(())
         }
 
-        fn update_object(
-            &mut self,
+        fn get_logs(
+            &self,
+            name: &str,
+            namespace: &str,
+            container: &str,
+            previous: bool,
+        ) -> anyhow::Result<String> {
+            Ok(format!(
+                "{}/{}/{} previous={}\n",
+                namespace, name, container, previous
+            ))
+        }
+
+        fn follow_logs(
+            &self,
+            _name: &str,
+            _namespace: &str,
+            _container: &str,
+            log: Arc<FollowedLog>,
+        ) -> anyhow::Result<()> {
+            log.append(b"2020-05-01T10:00:00Z started\n");
+            Ok(())
+        }
+
+        fn exec(
+            &self,
             name: &str,
             namespace: &str,
+            container: &str,
+            command: &[&str],
+            stdin: &[u8],
+        ) -> anyhow::Result<ExecOutput> {
+            let stdout = match command {
+                ["sh", "-c", script, "sh", "/"] if *script == pod::LIST_DIRECTORY => {
+                    String::from("etc/\nhostname\ngone\n")
+                }
+                ["sh", "-c", script, "sh", "/etc"] if *script == pod::LIST_DIRECTORY => {
+                    String::from("hosts\n")
+                }
+                ["cat", "--", path] => format!("contents of {}\n", path),
+                ["sh", "-c", "cat > \"$1\"", "sh", path] => {
+                    self.uploads
+                        .lock()
+                        .unwrap()
+                        .push((path.to_string(), stdin.to_vec()));
+                    String::new()
+                }
+                ["rm", "--", "/gone"] => {
+                    return Ok(ExecOutput {
+                        stderr: b"rm: can't remove '/gone': No such file or directory\n".to_vec(),
+                        ..ExecOutput::default()
+                    })
+                }
+                ["sh", "-c", line] => {
+                    format!("{}/{}/{} ran {}\n", namespace, name, container, line)
+                }
+                _ => String::new(),
+            };
+
+            Ok(ExecOutput {
+                stdout: stdout.into_bytes(),
+                stderr: vec![],
+                success: true,
+            })
+        }
+
+        fn patch_object(
+            &self,
+            name: &str,
+            _namespace: Option<&str>,
+            _object_name: &str,
+            patch: &Value,
+        ) -> anyhow::Result<KubeObjectEntry> {
+            self.patches.lock().unwrap().push(patch.clone());
+
+            Ok(KubeObjectEntry {
+                name: name.to_string(),
+                uid: Some(format!("{}-uid", name)),
+                stat: ObjectStat::default(),
+            })
+        }
+
+        fn update_object(
+            &self,
+            name: &str,
+            _namespace: Option<&str>,
+            _object_name: &str,
+            data: &str,
+            resource_version: Option<&str>,
+        ) -> anyhow::Result<KubeObjectEntry> {
+            self.updates
+                .lock()
+                .unwrap()
+                .push(resource_version.map(String::from));
+
+            Ok(KubeObjectEntry {
+                name: name.to_string(),
+                uid: Some(format!("{}-uid", name)),
+                stat: ObjectStat {
+                    size: data.len() as u64,
+                    created: Some(1000),
+                    modified: Some(3000),
+                    resource_version: Some(String::from("updated")),
+                },
+            })
+        }
+
+        fn create_object(
+            &self,
+            name: &str,
+            namespace: Option<&str>,
             object_name: &str,
             data: &str,
-        ) -> Result<(), anyhow::Error> {
-            Ok(())
+        ) -> anyhow::Result<KubeObjectEntry> {
+            if namespace == Some("default") && object_name == "deployments" {
+                Ok(KubeObjectEntry {
+                    name: data.trim_start_matches("name: ").to_string(),
+                    uid: Some(format!("{}-uid", name)),
+                    stat: ObjectStat::default(),
+                })
+            } else {
+                Err(KubeFSInodeError::MissingInode.into())
+            }
+        }
+
+        fn delete_object(
+            &self,
+            name: &str,
+            namespace: Option<&str>,
+            object_name: &str,
+            options: &DeleteOptions,
+        ) -> anyhow::Result<()> {
+            if mock_object_names(namespace, object_name).contains(&name) {
+                self.deletes
+                    .lock()
+                    .unwrap()
+                    .push((name.to_string(), options.clone()));
+                Ok(())
+            } else {
+                Err(KubeFSInodeError::MissingInode.into())
+            }
         }
 
-        fn remove_namespace(&mut self, _name: &str) -> anyhow::Result<()> {
+        fn remove_namespace(&self, _name: &str) -> anyhow::Result<()> {
             Ok(())
         }
     }
@@ -1,158 +1,1097 @@
-use crate::inode::K8sInteractions;
+use crate::{
+    cache::ObjectCache,
+    data::{self, SecretView},
+    inode::{DeleteOptions, ExecOutput, K8sInteractions, KubeObjectEntry, ObjectStat},
+    neat,
+    pod::FollowedLog,
+};
 use k8s_openapi::{
-    api::{
-        apps::v1::{Deployment, StatefulSet},
-        core::v1::{ConfigMap, Namespace, Pod, Secret, Service, ServiceAccount},
-    },
-    Resource,
+    api::core::v1::Namespace,
+    apimachinery::pkg::apis::meta::v1::{APIGroupList, APIResourceList},
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryFrom,
+    error::Error,
+    fmt::{self, Display},
+    future::Future,
+    hash::{Hash, Hasher},
+    ops::Add,
+    path::PathBuf,
+    process::Stdio,
+    sync::{mpsc, Arc, Mutex, RwLock, RwLockReadGuard},
+    time::{Duration, Instant},
 };
 
-use serde::{de::DeserializeOwned, ser::Serialize};
-use serde_json::json;
-use std::{clone::Clone, ops::Add};
+use log::warn;
 
 use kube::{
-    api::{ListParams, Meta, PostParams, DeleteParams},
-    Api, Client,
+    api::{DeleteParams, PostParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Api, Client, Config,
 };
 
-use tokio::runtime::Runtime;
+use tokio::{io::AsyncWriteExt, process::Command, runtime::Runtime, stream::StreamExt};
+
+/// A failed call to the API server, classified so that callers can tell
+/// apart rejections, unreachable servers and unknown kinds.
+#[derive(Debug)]
+pub enum KubeClientError {
+    /// The API server answered with a failure `Status`
+    Api {
+        code: u16,
+        reason: String,
+        message: String,
+    },
+    Timeout(String),
+    Connection(String),
+    /// A response which could not be read as what was asked for
+    Decode(String),
+    /// A request which could not be built, such as one for an invalid name
+    Request(String),
+    UnknownKind(String),
+    /// A manifest which does not belong in the directory it was written to
+    InvalidManifest(String),
+    /// kubectl, which execs into containers, could not be started
+    Exec(String),
+}
+
+impl Error for KubeClientError {}
+
+impl Display for KubeClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KubeClientError::Api {
+                code,
+                reason,
+                message,
+            } => write!(f, "{} ({}): {}", reason, code, message),
+            KubeClientError::Timeout(e) => write!(f, "Request timed out: {}", e),
+            KubeClientError::Connection(e) => write!(f, "Unable to reach API server: {}", e),
+            KubeClientError::Decode(e) => write!(f, "Unreadable response: {}", e),
+            KubeClientError::Request(e) => write!(f, "Invalid request: {}", e),
+            KubeClientError::UnknownKind(kind) => write!(f, "Unknown object kind {}", kind),
+            KubeClientError::InvalidManifest(e) => write!(f, "Invalid manifest: {}", e),
+            KubeClientError::Exec(e) => write!(f, "Unable to run kubectl: {}", e),
+        }
+    }
+}
+
+impl From<kube::Error> for KubeClientError {
+    fn from(e: kube::Error) -> Self {
+        match e {
+            kube::Error::Api(response) => KubeClientError::Api {
+                code: response.code,
+                reason: response.reason,
+                message: response.message,
+            },
+            kube::Error::ReqwestError(e) if e.is_timeout() => {
+                KubeClientError::Timeout(e.to_string())
+            }
+            kube::Error::ReqwestError(e) if e.is_builder() => {
+                KubeClientError::Request(e.to_string())
+            }
+            e @ kube::Error::SerdeError(_) | e @ kube::Error::RequestParse => {
+                KubeClientError::Decode(format!("{:#}", anyhow::Error::from(e)))
+            }
+            e @ kube::Error::HttpError(_)
+            | e @ kube::Error::InternalUrlError(_)
+            | e @ kube::Error::RequestBuild
+            | e @ kube::Error::InvalidMethod(_)
+            | e @ kube::Error::RequestValidation(_) => {
+                KubeClientError::Request(format!("{:#}", anyhow::Error::from(e)))
+            }
+            // What is left fails before a response arrives: sending the
+            // request, TLS and loading credentials
+            e => KubeClientError::Connection(e.to_string()),
+        }
+    }
+}
+
+/// A resource kind served by the API server as found through discovery.
+#[derive(Debug, Clone)]
+struct ApiResource {
+    group: String,
+    version: String,
+    plural: String,
+    kind: String,
+    namespaced: bool,
+}
+
+impl ApiResource {
+    fn is_secret(&self) -> bool {
+        self.group.is_empty() && self.plural == data::SECRET_KIND
+    }
+
+    fn api_version(&self) -> String {
+        if self.group.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{}/{}", self.group, self.version)
+        }
+    }
+
+    fn url(&self, namespace: Option<&str>, name: Option<&str>) -> String {
+        let prefix = if self.group.is_empty() { "api" } else { "apis" };
+
+        let mut url = format!("/{}/{}/", prefix, self.api_version());
+
+        if let Some(namespace) = namespace {
+            url.push_str(&format!("namespaces/{}/", namespace));
+        }
+
+        url.push_str(&self.plural);
+
+        if let Some(name) = name {
+            url.push('/');
+            url.push_str(name);
+        }
+
+        url
+    }
+}
+
+/// Checks a manifest written into a kind directory against that directory
+/// and fills in the name and namespace when they are left out. Returns the
+/// resource at the version the manifest was written for.
+fn prepare_new_manifest(
+    resource: &ApiResource,
+    name: &str,
+    namespace: Option<&str>,
+    data: &str,
+) -> anyhow::Result<(ApiResource, Value)> {
+    let invalid = |e: String| -> anyhow::Error { KubeClientError::InvalidManifest(e).into() };
+
+    let mut o = parse_manifest(data)?;
+    if !o.is_object() {
+        return Err(invalid(String::from("manifest is not a mapping")));
+    }
+    if !matches!(o.get("metadata"), None | Some(Value::Object(_))) {
+        return Err(invalid(String::from("metadata is not a mapping")));
+    }
+
+    let kind = o["kind"].as_str().unwrap_or_default();
+    if kind != resource.kind {
+        return Err(invalid(format!(
+            "kind {:?} does not belong in a directory of {}",
+            kind, resource.kind
+        )));
+    }
+
+    let api_version = o["apiVersion"].as_str().unwrap_or_default().to_string();
+    let (group, version) = match api_version.rfind('/') {
+        Some(i) => (&api_version[..i], &api_version[i + 1..]),
+        None => ("", api_version.as_str()),
+    };
+    if group != resource.group || version.is_empty() {
+        return Err(invalid(format!(
+            "apiVersion {:?} does not belong in a directory of {}",
+            api_version,
+            resource.api_version()
+        )));
+    }
+
+    match (namespace, o["metadata"]["namespace"].as_str()) {
+        (Some(namespace), Some(manifest_namespace)) if namespace != manifest_namespace => {
+            return Err(invalid(format!(
+                "namespace {} does not match directory namespace {}",
+                manifest_namespace, namespace
+            )));
+        }
+        (None, Some(_)) => {
+            return Err(invalid(String::from(
+                "cluster-scoped objects cannot set a namespace",
+            )));
+        }
+        (Some(namespace), None) => o["metadata"]["namespace"] = json!(namespace),
+        _ => {}
+    }
+
+    if o["metadata"]["name"].as_str().is_none() {
+        let name = [".yaml", ".yml", ".json"]
+            .iter()
+            .fold(name, |name, ext| name.trim_end_matches(ext));
+        o["metadata"]["name"] = json!(name);
+    }
+
+    let resource = ApiResource {
+        version: version.to_string(),
+        ..resource.clone()
+    };
+
+    Ok((resource, o))
+}
+
+/// The format object files are served in.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum ManifestFormat {
+    Yaml,
+    Json,
+}
+
+impl std::str::FromStr for ManifestFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(ManifestFormat::Yaml),
+            "json" => Ok(ManifestFormat::Json),
+            _ => Err(anyhow::anyhow!("Unknown manifest format {}", s)),
+        }
+    }
+}
+
+impl ManifestFormat {
+    /// Renders an object the way it is served from an object file.
+    fn render(self, o: &Value) -> anyhow::Result<String> {
+        Ok(match self {
+            ManifestFormat::Yaml => serde_yaml::to_string(o)?.add("\n"),
+            ManifestFormat::Json => serde_json::to_string_pretty(o)?.add("\n"),
+        })
+    }
+}
+
+/// How objects are turned into the manifests served for them.
+#[derive(Debug, Clone, Copy, Hash)]
+struct ManifestView {
+    secret_view: SecretView,
+    /// Leaves server-populated fields out of manifests
+    neat: bool,
+    format: ManifestFormat,
+}
+
+impl ManifestView {
+    /// An object as served by the API server with Secret values shown the
+    /// way they are configured to be.
+    fn object(&self, resource: &ApiResource, mut o: Value) -> Value {
+        set_type(resource, &mut o);
+        if resource.is_secret() {
+            self.secret_view.apply(&mut o);
+        }
+
+        o
+    }
+
+    /// Identifies the view, so sizes rendered by another one are not used.
+    fn key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The manifest served for an object.
+    fn render(&self, resource: &ApiResource, o: Value) -> anyhow::Result<String> {
+        let mut o = self.object(resource, o);
+        if self.neat {
+            neat::strip(&mut o);
+        }
+
+        self.format.render(&o)
+    }
+}
+
+/// Field manager edits are applied as with server-side apply.
+const FIELD_MANAGER: &str = "kubefs";
+
+/// Wait before resuming a log stream, doubled after each failure up to
+/// `MAX_FOLLOW_DELAY`.
+const FOLLOW_DELAY: Duration = Duration::from_secs(2);
+const MAX_FOLLOW_DELAY: Duration = Duration::from_secs(60);
+
+/// How an edited manifest is written back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteMode {
+    /// Replaces the whole object with the manifest
+    Replace,
+    /// Server-side apply, owning the fields the manifest sets. Fields owned
+    /// by another manager fail the write unless `force` takes them over.
+    Apply { force: bool },
+}
+
+/// Builds the server-side apply request for an edited manifest. The fields
+/// the server populates are left out: apply rejects managedFields, and
+/// without a resourceVersion an edit of a stale copy still applies.
+fn apply_request(
+    resource: &ApiResource,
+    namespace: Option<&str>,
+    name: &str,
+    mut o: Value,
+    force: bool,
+) -> anyhow::Result<http::Request<Vec<u8>>> {
+    neat::strip(&mut o);
+
+    Ok(http::Request::patch(format!(
+        "{}?fieldManager={}&force={}",
+        resource.url(namespace, Some(name)),
+        FIELD_MANAGER,
+        force
+    ))
+    .header(http::header::CONTENT_TYPE, "application/apply-patch+yaml")
+    .body(serde_json::to_vec(&o)?)?)
+}
+
+/// Parses a manifest written to the mount. JSON is parsed as JSON rather
+/// than as the YAML it also is, as YAML rejects the tabs JSON may be
+/// indented with.
+fn parse_manifest(data: &str) -> anyhow::Result<Value> {
+    if data.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(data)?)
+    } else {
+        Ok(serde_yaml::from_str(data)?)
+    }
+}
+
+/// Parses a Kubernetes timestamp into seconds since the epoch.
+fn timestamp(value: &Value) -> Option<i64> {
+    let tm = time::strptime(value.as_str()?, "%Y-%m-%dT%H:%M:%SZ").ok()?;
+
+    Some(tm.to_timespec().sec)
+}
+
+/// Sets apiVersion and kind, which list items and cached objects lack.
+fn set_type(resource: &ApiResource, o: &mut Value) {
+    o["apiVersion"] = json!(resource.api_version());
+    o["kind"] = json!(resource.kind);
+}
+
+/// Keeps a cached listing current until it is evicted. The server ends each
+/// watch after `timeoutSeconds`, which bounds how long a watch outlives its
+/// listing; until then it resumes from the last resource version seen.
+async fn watch(client: Client, cache: ObjectCache, url: String, generation: u64) {
+    let timeout = cache.ttl().as_secs().clamp(1, 300);
+
+    while let Some(version) = cache.resume_from(&url, generation) {
+        let request = http::Request::get(format!(
+            "{}?watch=1&allowWatchBookmarks=true&resourceVersion={}&timeoutSeconds={}",
+            url, version, timeout
+        ))
+        .body(vec![]);
+
+        let stream = match request {
+            Ok(request) => client.request_text_stream(request).await,
+            Err(e) => {
+                warn!("Unable to build watch for {}: {}", url, e);
+                break;
+            }
+        };
+        let mut stream = match stream {
+            Ok(stream) => Box::pin(stream),
+            Err(e) => {
+                warn!("Unable to watch {}: {}", url, e);
+                break;
+            }
+        };
+
+        // Events are newline separated, but chunks can split them anywhere
+        let mut buffer = vec![];
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(&chunk),
+                Err(e) => {
+                    warn!("Watch of {} failed: {}", url, e);
+                    break;
+                }
+            }
+
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let event: Value = match serde_json::from_slice(&line) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        warn!("Unreadable watch event for {}: {}", url, e);
+                        continue;
+                    }
+                };
+
+                if !cache.apply(&url, generation, &event) {
+                    return;
+                }
+            }
+        }
+    }
+
+    cache.remove(&url, generation);
+}
+
+/// Appends the output of a container to `log` until the log is closed. The
+/// API server ends log streams after a while and they end when the container
+/// stops, so the stream is resumed from the second of the last line seen.
+///
+/// Streams are handed out without their status, so each one is preceded by
+/// a request for a single byte of the same log. Failures go to `log` instead
+/// of its output; following stops once the pod is gone or may not be read,
+/// and otherwise backs off.
+async fn follow_logs(client: Client, url: String, log: Arc<FollowedLog>) {
+    let mut delay = FOLLOW_DELAY;
+
+    while !log.is_closed() {
+        let since = log
+            .since_time()
+            .map(|since| format!("&sinceTime={}", since))
+            .unwrap_or_default();
+        let requests = (
+            http::Request::get(format!("{}&limitBytes=1{}", url, since)).body(vec![]),
+            http::Request::get(format!("{}&follow=true{}", url, since)).body(vec![]),
+        );
+
+        let stream = match requests {
+            (Ok(probe), Ok(request)) => match client.request_text(probe).await {
+                Ok(_) => client.request_text_stream(request).await,
+                Err(e) => Err(e),
+            },
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Unable to build log request for {}: {}", url, e);
+                return;
+            }
+        };
+
+        log.resume();
+        match stream {
+            Ok(stream) => {
+                let mut stream = Box::pin(stream);
+                delay = FOLLOW_DELAY;
+
+                // Checked every second so a quiet log stops soon after close
+                while !log.is_closed() {
+                    match tokio::time::timeout(Duration::from_secs(1), stream.next()).await {
+                        Ok(Some(Ok(chunk))) => log.append(&chunk),
+                        Ok(Some(Err(e))) => {
+                            warn!("Following {} failed: {}", url, e);
+                            break;
+                        }
+                        Ok(None) => break,
+                        Err(_) => {}
+                    }
+                }
+            }
+            Err(e) => {
+                let e = KubeClientError::from(e);
+                warn!("Unable to follow {}: {}", url, e);
+
+                let gone = matches!(
+                    e,
+                    KubeClientError::Api {
+                        code: 403 | 404,
+                        ..
+                    }
+                );
+                log.fail(e.into());
+                if gone {
+                    return;
+                }
+
+                delay = (delay * 2).min(MAX_FOLLOW_DELAY);
+            }
+        }
+
+        tokio::time::delay_for(delay).await;
+    }
+}
+
+/// Builds the entry for an object as returned by the API server. Its size is
+/// that of the manifest served for it, which is rendered to find out once
+/// per version of an object in a cached listing at `url`.
+fn object_entry(
+    resource: &ApiResource,
+    o: Value,
+    view: &ManifestView,
+    cache: &ObjectCache,
+    url: &str,
+) -> Option<KubeObjectEntry> {
+    let metadata = &o["metadata"];
+    let name = metadata["name"].as_str()?;
+    let version = metadata["resourceVersion"].as_str().unwrap_or_default();
+
+    let size = match cache.size(url, name, version, view.key()) {
+        Some(size) => size,
+        None => {
+            let size = view.render(resource, o.clone()).ok()?.len() as u64;
+            cache.set_size(url, name, version, view.key(), size);
+            size
+        }
+    };
+
+    let modified = metadata["managedFields"]
+        .as_array()
+        .and_then(|fields| fields.iter().filter_map(|f| timestamp(&f["time"])).max());
+
+    Some(KubeObjectEntry {
+        name: name.to_string(),
+        uid: metadata["uid"].as_str().map(String::from),
+        stat: ObjectStat {
+            size,
+            created: timestamp(&metadata["creationTimestamp"]),
+            modified,
+            resource_version: metadata["resourceVersion"].as_str().map(String::from),
+        },
+    })
+}
+
+/// Which cluster to connect to and as whom, taken from the command line.
+/// Anything left out is found the way kubectl finds it.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    pub kubeconfig: Option<PathBuf>,
+    pub context: Option<String>,
+    pub impersonate_user: Option<String>,
+    pub impersonate_groups: Vec<String>,
+}
+
+impl ConnectOptions {
+    fn read_kubeconfig(&self) -> anyhow::Result<Kubeconfig> {
+        Ok(match &self.kubeconfig {
+            Some(path) => Kubeconfig::read_from(path)?,
+            None => Kubeconfig::read()?,
+        })
+    }
+
+    async fn config(&self) -> anyhow::Result<Config> {
+        let options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..KubeConfigOptions::default()
+        };
+
+        let mut config = match (&self.kubeconfig, &self.context) {
+            (Some(_), _) => {
+                Config::from_custom_kubeconfig(self.read_kubeconfig()?, &options).await?
+            }
+            (None, Some(_)) => Config::from_kubeconfig(&options).await?,
+            (None, None) => Config::infer().await?,
+        };
+
+        if let Some(user) = &self.impersonate_user {
+            config
+                .headers
+                .insert("Impersonate-User", http::HeaderValue::from_str(user)?);
+        }
+        for group in &self.impersonate_groups {
+            config
+                .headers
+                .append("Impersonate-Group", http::HeaderValue::from_str(group)?);
+        }
+
+        Ok(config)
+    }
+
+    /// The same options as kubectl flags, for the calls made through it.
+    fn kubectl_args(&self) -> Vec<String> {
+        let mut args = vec![];
+
+        if let Some(kubeconfig) = &self.kubeconfig {
+            args.push(format!("--kubeconfig={}", kubeconfig.display()));
+        }
+        if let Some(context) = &self.context {
+            args.push(format!("--context={}", context));
+        }
+        if let Some(user) = &self.impersonate_user {
+            args.push(format!("--as={}", user));
+        }
+        for group in &self.impersonate_groups {
+            args.push(format!("--as-group={}", group));
+        }
+
+        args
+    }
+}
+
+/// Names of the contexts in the kubeconfig.
+pub fn kubeconfig_contexts(options: &ConnectOptions) -> anyhow::Result<Vec<String>> {
+    Ok(options
+        .read_kubeconfig()?
+        .contexts
+        .into_iter()
+        .map(|context| context.name)
+        .collect())
+}
 
 pub struct KubeClient {
     client: Client,
     runtime: Runtime,
+    resources: RwLock<HashMap<String, ApiResource>>,
+    /// When discovery last ran. Its results are reused for the cache TTL.
+    discovered: Mutex<Option<Instant>>,
+    cache: ObjectCache,
+    timeout: Duration,
+    options: ConnectOptions,
+    view: ManifestView,
+    write_mode: WriteMode,
 }
 
 impl KubeClient {
-    pub fn new() -> Self {
-        let mut runtime = Runtime::new().unwrap();
+    pub fn new(
+        options: &ConnectOptions,
+        cache_ttl: Duration,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let mut runtime = Runtime::new()?;
 
-        KubeClient {
-            client: runtime.block_on(Client::try_default()).unwrap(),
-            runtime: runtime,
-        }
+        let client = Client::try_from(runtime.block_on(options.config())?)?;
+
+        Ok(KubeClient {
+            client,
+            runtime,
+            resources: RwLock::new(HashMap::new()),
+            discovered: Mutex::new(None),
+            cache: ObjectCache::new(cache_ttl),
+            timeout,
+            options: options.clone(),
+            view: ManifestView {
+                secret_view: SecretView::Encoded,
+                neat: false,
+                format: ManifestFormat::Yaml,
+            },
+            write_mode: WriteMode::Replace,
+        })
     }
 
-    fn get_object_names<T: Resource + Clone + DeserializeOwned + Meta>(
-        &mut self,
-        namespace: &str,
-    ) -> Result<Vec<String>, anyhow::Error> {
-        let objects: Api<T> = Api::<T>::namespaced(self.client.clone(), namespace);
+    pub fn with_secret_view(mut self, secret_view: SecretView) -> Self {
+        self.view.secret_view = secret_view;
+        self
+    }
 
-        let lp = ListParams::default();
+    pub fn with_neat(mut self, neat: bool) -> Self {
+        self.view.neat = neat;
+        self
+    }
 
-        let object_list = self.runtime.block_on(objects.list(&lp))?;
+    pub fn with_format(mut self, format: ManifestFormat) -> Self {
+        self.view.format = format;
+        self
+    }
 
-        Ok(object_list.iter().map(|o| Meta::name(o)).collect())
+    pub fn with_write_mode(mut self, write_mode: WriteMode) -> Self {
+        self.write_mode = write_mode;
+        self
     }
 
-    fn get_object<T: Resource + Clone + DeserializeOwned + Meta + Serialize>(
-        &mut self,
+    /// An object as stored, from the cache when its kind is cached.
+    fn get_stored_object(
+        &self,
+        resource: &ApiResource,
         name: &str,
-        namespace: &str,
-    ) -> Result<String, anyhow::Error> {
-        let objects: Api<T> = Api::<T>::namespaced(self.client.clone(), namespace);
+        namespace: Option<&str>,
+    ) -> anyhow::Result<Value> {
+        match self.cache.get(&resource.url(namespace, None), name) {
+            Some(o) => Ok(o),
+            None => self.get(&resource.url(namespace, Some(name))),
+        }
+    }
+
+    /// Runs a call to the API server on the runtime and waits for it,
+    /// converting failures into a `KubeClientError`. Calls still running
+    /// after the request timeout are dropped, which cancels them.
+    fn block_on<T: Send + 'static>(
+        &self,
+        f: impl Future<Output = kube::Result<T>> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        Ok(self.run(f)?.map_err(KubeClientError::from)?)
+    }
+
+    /// Runs any future on the runtime with the request timeout.
+    fn run<T: Send + 'static>(
+        &self,
+        f: impl Future<Output = T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let (tx, rx) = mpsc::channel();
+        let timeout = self.timeout;
 
-        let o = self.runtime.block_on(objects.get(name))?;
+        self.runtime.spawn(async move {
+            let _ = tx.send(tokio::time::timeout(timeout, f).await);
+        });
 
-        Ok(serde_yaml::to_string(&o)?.add("\n"))
+        match rx.recv() {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(KubeClientError::Timeout(format!(
+                "No response within {} seconds",
+                timeout.as_secs()
+            ))
+            .into()),
+            Err(_) => Err(KubeClientError::Connection(String::from("Request was dropped")).into()),
+        }
     }
 
-    fn update_object<T: Resource + Clone + DeserializeOwned + Meta + Serialize>(
-        &mut self,
-        name: &str,
-        namespace: &str,
-        data: &str,
-    ) -> anyhow::Result<()> {
-        let objects: Api<T> = Api::<T>::namespaced(self.client.clone(), namespace);
+    fn request<T: DeserializeOwned + Send + 'static>(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> anyhow::Result<T> {
+        let client = self.client.clone();
+        self.block_on(async move { client.request::<T>(request).await })
+    }
+
+    fn get<T: DeserializeOwned + Send + 'static>(&self, url: &str) -> anyhow::Result<T> {
+        self.request(http::Request::get(url).body(vec![])?)
+    }
 
-        let pp = PostParams::default();
-        
-        let o : T = serde_yaml::from_str(data)?;
+    /// Queries the discovery endpoints for every resource which can be listed.
+    /// Resources are keyed by their plural name, falling back to
+    /// `plural.group` when two API groups serve the same plural.
+    fn discover_resources(&self) -> anyhow::Result<()> {
+        let mut resource_lists: Vec<(String, APIResourceList)> = vec![];
 
-        self.runtime.block_on(objects.replace(name, &pp, &o))?;
+        let core: APIResourceList = self.get("/api/v1")?;
+        resource_lists.push((String::new(), core));
+
+        let groups: APIGroupList = self.get("/apis")?;
+        for group in groups.groups {
+            if let Some(preferred) = group.preferred_version {
+                // Aggregated APIs and CRDs whose conversion webhooks are down
+                // fail discovery individually, so skip them rather than
+                // hiding every other kind.
+                match self.get::<APIResourceList>(&format!("/apis/{}", preferred.group_version)) {
+                    Ok(list) => resource_lists.push((group.name, list)),
+                    Err(e) => warn!("Skipping API group {}: {}", preferred.group_version, e),
+                }
+            }
+        }
+
+        let mut resources = HashMap::new();
+
+        for (group, list) in resource_lists {
+            let version = match list.group_version.split('/').next_back() {
+                Some(version) => version.to_string(),
+                None => continue,
+            };
+
+            for r in list.resources {
+                // Subresources such as pods/log are served as `<plural>/<sub>`
+                if r.name.contains('/') || !r.verbs.iter().any(|v| v == "list") {
+                    continue;
+                }
+
+                let key = if resources.contains_key(&r.name) {
+                    format!("{}.{}", r.name, group)
+                } else {
+                    r.name.clone()
+                };
+
+                resources.insert(
+                    key,
+                    ApiResource {
+                        group: group.clone(),
+                        version: version.clone(),
+                        plural: r.name,
+                        kind: r.kind,
+                        namespaced: r.namespaced,
+                    },
+                );
+            }
+        }
+
+        *self.resources.write().unwrap_or_else(|e| e.into_inner()) = resources;
 
         Ok(())
     }
-}
 
-impl K8sInteractions for KubeClient {
-    fn get_namespaces(&mut self) -> Result<Vec<String>, anyhow::Error> {
-        let namespaces: Api<Namespace> = Api::all(self.client.clone());
-        let lp = ListParams::default();
+    /// Runs discovery unless it already ran within the cache TTL, so that
+    /// listing kinds doesn't query every API group each time.
+    fn refresh_resources(&self) -> anyhow::Result<()> {
+        // Held while discovering, so concurrent lookups wait for one run
+        let mut discovered = self.discovered.lock().unwrap_or_else(|e| e.into_inner());
+        if discovered.is_some_and(|at| at.elapsed() < self.cache.ttl()) {
+            return Ok(());
+        }
+
+        self.discover_resources()?;
+        *discovered = Some(Instant::now());
+
+        Ok(())
+    }
 
-        let ns = self.runtime.block_on(namespaces.list(&lp))?;
-        let mut res: Vec<String> = vec![];
+    /// Lists a kind from the cache, or from the API server when it is not
+    /// cached yet, in which case a watch is started to keep it cached.
+    fn list(&self, resource: &ApiResource, namespace: Option<&str>) -> anyhow::Result<Vec<Value>> {
+        let url = resource.url(namespace, None);
 
-        for n in ns {
-            let name = Meta::name(&n);
-            res.push(name);
+        if let Some(items) = self.cache.list(&url) {
+            return Ok(items);
         }
 
+        let mut list: Value = self.get(&url)?;
+        let items = match list["items"].take() {
+            Value::Array(items) => items,
+            _ => vec![],
+        };
+
+        if self.cache.enabled() {
+            let version = list["metadata"]["resourceVersion"]
+                .as_str()
+                .unwrap_or_default();
+            let generation = self.cache.insert_list(&url, items.clone(), version);
+
+            self.runtime.spawn(watch(
+                self.client.clone(),
+                self.cache.clone(),
+                url,
+                generation,
+            ));
+        }
+
+        Ok(items)
+    }
+
+    fn resources(&self) -> RwLockReadGuard<'_, HashMap<String, ApiResource>> {
+        self.resources.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get_resource(&self, object_name: &str) -> anyhow::Result<ApiResource> {
+        // A miss may be a CRD installed since the last discovery
+        if let Some(resource) = self.resources().get(object_name) {
+            return Ok(resource.clone());
+        }
+
+        self.refresh_resources()?;
+
+        self.resources()
+            .get(object_name)
+            .cloned()
+            .ok_or_else(|| KubeClientError::UnknownKind(object_name.to_string()).into())
+    }
+}
+
+impl K8sInteractions for KubeClient {
+    fn get_namespaces(&self) -> Result<Vec<String>, anyhow::Error> {
+        let res = self
+            .get_objects(None, "namespaces")?
+            .into_iter()
+            .map(|o| o.name)
+            .collect();
+
         Ok(res)
     }
 
+    fn get_object_kinds(&self, namespaced: bool) -> Result<Vec<String>, anyhow::Error> {
+        self.refresh_resources()?;
+
+        let mut kinds: Vec<String> = self
+            .resources()
+            .iter()
+            .filter(|(_, r)| r.namespaced == namespaced)
+            .map(|(name, _)| name.clone())
+            .collect();
+        kinds.sort();
+
+        Ok(kinds)
+    }
+
     fn get_objects(
-        &mut self,
-        namespace: &str,
+        &self,
+        namespace: Option<&str>,
         object_name: &str,
-    ) -> Result<Vec<String>, anyhow::Error> {
-        let res = match object_name {
-            "deployments" => self.get_object_names::<Deployment>(namespace)?,
-            "pods" => self.get_object_names::<Pod>(namespace)?,
-            "services" => self.get_object_names::<Service>(namespace)?,
-            "statefulsets" => self.get_object_names::<StatefulSet>(namespace)?,
-            "configmaps" => self.get_object_names::<ConfigMap>(namespace)?,
-            "secrets" => self.get_object_names::<Secret>(namespace)?,
-            "serviceaccounts" => self.get_object_names::<ServiceAccount>(namespace)?,
-            _ => vec![],
-        };
+    ) -> Result<Vec<KubeObjectEntry>, anyhow::Error> {
+        let resource = self.get_resource(object_name)?;
+        let url = resource.url(namespace, None);
+        let res = self
+            .list(&resource, namespace)?
+            .into_iter()
+            .filter_map(|o| object_entry(&resource, o, &self.view, &self.cache, &url))
+            .collect();
 
         Ok(res)
     }
 
     fn update_object(
-        &mut self,
+        &self,
         name: &str,
-        namespace: &str,
+        namespace: Option<&str>,
         object_name: &str,
         data: &str,
-    ) -> Result<(), anyhow::Error> {
-        match object_name {
-            "deployments" => self.update_object::<Deployment>(name, namespace, data)?,
-            "pods" => self.update_object::<Pod>(name, namespace, data)?,
-            "services" => self.update_object::<Service>(name, namespace, data)?,
-            "statefulsets" => self.update_object::<StatefulSet>(name, namespace, data)?,
-            "configmaps" => self.update_object::<ConfigMap>(name, namespace, data)?,
-            "secrets" => self.update_object::<Secret>(name, namespace, data)?,
-            "serviceaccounts" => self.update_object::<ServiceAccount>(name, namespace, data)?,
-            _ => {},
+        resource_version: Option<&str>,
+    ) -> anyhow::Result<KubeObjectEntry> {
+        let resource = self.get_resource(object_name)?;
+        let mut o = parse_manifest(data)?;
+
+        // Apply only sends what the manifest sets, so fields left out of a
+        // neat manifest need no restoring
+        let redacted = resource.is_secret() && self.view.secret_view == SecretView::Redacted;
+        let restore_neat = self.view.neat && self.write_mode == WriteMode::Replace;
+        if restore_neat || redacted {
+            let current = self.get_stored_object(&resource, name, namespace)?;
+
+            if redacted {
+                data::restore_redacted(&mut o, &current)
+                    .map_err(|e| KubeClientError::InvalidManifest(e.to_string()))?;
+            }
+            if restore_neat {
+                neat::restore(&mut o, &current, resource_version)
+                    .map_err(|e| KubeClientError::InvalidManifest(e.to_string()))?;
+            }
+        }
+
+        let request = match self.write_mode {
+            WriteMode::Replace => http::Request::put(resource.url(namespace, Some(name)))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&o)?)?,
+            WriteMode::Apply { force } => apply_request(&resource, namespace, name, o, force)?,
         };
 
-        Ok(())
+        let updated: Value = self.request(request)?;
+        self.cache.upsert(&resource.url(namespace, None), &updated);
+
+        object_entry(
+            &resource,
+            updated,
+            &self.view,
+            &self.cache,
+            &resource.url(namespace, None),
+        )
+        .ok_or_else(|| anyhow::anyhow!("Updated object {} has no name", name))
+    }
+
+    fn patch_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+        patch: &Value,
+    ) -> anyhow::Result<KubeObjectEntry> {
+        let resource = self.get_resource(object_name)?;
+
+        let request = http::Request::patch(resource.url(namespace, Some(name)))
+            .header(http::header::CONTENT_TYPE, "application/merge-patch+json")
+            .body(serde_json::to_vec(patch)?)?;
+
+        let patched: Value = self.request(request)?;
+        self.cache.upsert(&resource.url(namespace, None), &patched);
+
+        object_entry(
+            &resource,
+            patched,
+            &self.view,
+            &self.cache,
+            &resource.url(namespace, None),
+        )
+        .ok_or_else(|| anyhow::anyhow!("Patched object {} has no name", name))
+    }
+
+    fn get_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+    ) -> anyhow::Result<Value> {
+        let resource = self.get_resource(object_name)?;
+
+        let o = self.get_stored_object(&resource, name, namespace)?;
+
+        Ok(self.view.object(&resource, o))
     }
 
     fn get_object_data_as_yaml(
-        &mut self,
+        &self,
         name: &str,
-        namespace: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+    ) -> anyhow::Result<(String, Option<String>)> {
+        let resource = self.get_resource(object_name)?;
+        let o = self.get_stored_object(&resource, name, namespace)?;
+        let resource_version = o["metadata"]["resourceVersion"].as_str().map(String::from);
+
+        // The same rendering sizes the entries of listings
+        Ok((self.view.render(&resource, o)?, resource_version))
+    }
+
+    fn create_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
+        object_name: &str,
+        data: &str,
+    ) -> anyhow::Result<KubeObjectEntry> {
+        let listed = self.get_resource(object_name)?;
+        let (resource, o) = prepare_new_manifest(&listed, name, namespace, data)?;
+
+        let request = http::Request::post(resource.url(namespace, None))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&o)?)?;
+
+        let created: Value = self.request(request)?;
+        if resource.version == listed.version {
+            self.cache.upsert(&listed.url(namespace, None), &created);
+        }
+
+        object_entry(
+            &resource,
+            created,
+            &self.view,
+            &self.cache,
+            &resource.url(namespace, None),
+        )
+        .ok_or_else(|| anyhow::anyhow!("Created object {} has no name", name))
+    }
+
+    fn delete_object(
+        &self,
+        name: &str,
+        namespace: Option<&str>,
         object_name: &str,
+        options: &DeleteOptions,
+    ) -> anyhow::Result<()> {
+        let resource = self.get_resource(object_name)?;
+
+        let mut body = json!({
+            "apiVersion": "v1",
+            "kind": "DeleteOptions",
+        });
+        if let Some(policy) = &options.propagation_policy {
+            body["propagationPolicy"] = json!(policy);
+        }
+        if let Some(seconds) = options.grace_period_seconds {
+            body["gracePeriodSeconds"] = json!(seconds);
+        }
+
+        let request = http::Request::delete(resource.url(namespace, Some(name)))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)?;
+
+        self.request::<Value>(request)?;
+
+        Ok(())
+    }
+
+    fn get_logs(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        previous: bool,
     ) -> anyhow::Result<String> {
-        let data = match object_name {
-            "deployments" => self.get_object::<Deployment>(name, namespace)?,
-            "pods" => self.get_object::<Pod>(name, namespace)?,
-            "services" => self.get_object::<Service>(name, namespace)?,
-            "statefulsets" => self.get_object::<StatefulSet>(name, namespace)?,
-            "configmaps" => self.get_object::<ConfigMap>(name, namespace)?,
-            "secrets" => self.get_object::<Secret>(name, namespace)?,
-            "serviceaccounts" => self.get_object::<ServiceAccount>(name, namespace)?,
-            _ => String::new(),
-        };
+        let request = http::Request::get(format!(
+            "/api/v1/namespaces/{}/pods/{}/log?container={}&previous={}",
+            namespace, name, container, previous
+        ))
+        .body(vec![])?;
 
-        Ok(data)
+        let client = self.client.clone();
+        self.block_on(async move { client.request_text(request).await })
     }
 
-    fn create_namespace(&mut self, name: &str) -> anyhow::Result<()> {
+    fn follow_logs(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        log: Arc<FollowedLog>,
+    ) -> anyhow::Result<()> {
+        let url = format!(
+            "/api/v1/namespaces/{}/pods/{}/log?container={}&timestamps=true",
+            namespace, name, container
+        );
+
+        let request = http::Request::get(&url).body(vec![])?;
+        let client = self.client.clone();
+        let output = self.block_on(async move { client.request_text(request).await })?;
+        log.append(output.as_bytes());
+
+        self.runtime
+            .spawn(follow_logs(self.client.clone(), url, log));
+
+        Ok(())
+    }
+
+    fn create_namespace(&self, name: &str) -> anyhow::Result<()> {
         let namespaces: Api<Namespace> = Api::all(self.client.clone());
         let ns: Namespace = serde_json::from_value(json!({
             "apiVersion": "v1",
@@ -160,18 +1099,317 @@ impl K8sInteractions for KubeClient {
             "metadata": { "name": name }
         }))?;
 
-        let pp = PostParams::default();
-
-        self.runtime.block_on(namespaces.create(&pp, &ns))?;
+        self.block_on(async move { namespaces.create(&PostParams::default(), &ns).await })?;
 
         Ok(())
     }
 
-    fn remove_namespace(&mut self, name: &str) -> anyhow::Result<()> {
+    /// The API server only offers exec as a streaming upgrade, which the
+    /// client does not speak, so commands run through kubectl with the same
+    /// kubeconfig, context and impersonation as the mount. A command still
+    /// running at the request timeout is killed.
+    fn exec(
+        &self,
+        name: &str,
+        namespace: &str,
+        container: &str,
+        command: &[&str],
+        stdin: &[u8],
+    ) -> anyhow::Result<ExecOutput> {
+        let mut kubectl = Command::new("kubectl");
+        kubectl
+            .args(self.options.kubectl_args())
+            .args([
+                "exec",
+                "--namespace",
+                namespace,
+                name,
+                "--container",
+                container,
+            ])
+            .args(if stdin.is_empty() {
+                None
+            } else {
+                Some("--stdin")
+            })
+            .arg("--")
+            .args(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let stdin = stdin.to_vec();
+        let output = self
+            .run(async move {
+                let mut child = kubectl.spawn()?;
+
+                // Written alongside reading the output, so neither side can
+                // fill its pipe and stall the other
+                if let Some(mut input) = child.stdin.take() {
+                    tokio::spawn(async move {
+                        let _ = input.write_all(&stdin).await;
+                    });
+                }
+
+                child.wait_with_output().await
+            })?
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => KubeClientError::Exec(String::from(
+                    "kubectl was not found on the PATH, it is needed to run commands in containers",
+                )),
+                _ => KubeClientError::Exec(e.to_string()),
+            })?;
+
+        Ok(ExecOutput {
+            stdout: output.stdout,
+            stderr: output.stderr,
+            success: output.status.success(),
+        })
+    }
+
+    fn remove_namespace(&self, name: &str) -> anyhow::Result<()> {
         let namespaces: Api<Namespace> = Api::all(self.client.clone());
-        let dp = DeleteParams::default();
-        self.runtime.block_on(namespaces.delete(&name, &dp))?;
+        let name = name.to_string();
+        self.block_on(async move { namespaces.delete(&name, &DeleteParams::default()).await })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deployments() -> ApiResource {
+        ApiResource {
+            group: String::from("apps"),
+            version: String::from("v1"),
+            plural: String::from("deployments"),
+            kind: String::from("Deployment"),
+            namespaced: true,
+        }
+    }
+
+    #[test]
+    fn test_kube_errors_are_classified() {
+        let decode = serde_json::from_str::<Value>("{").unwrap_err();
+        let build = http::Request::get("/")
+            .header("a\n", "b")
+            .body(())
+            .unwrap_err();
+
+        assert!(matches!(
+            KubeClientError::from(kube::Error::SerdeError(decode)),
+            KubeClientError::Decode(_)
+        ));
+        assert!(matches!(
+            KubeClientError::from(kube::Error::HttpError(build)),
+            KubeClientError::Request(_)
+        ));
+        assert!(matches!(
+            KubeClientError::from(kube::Error::RequestBuild),
+            KubeClientError::Request(_)
+        ));
+        assert!(matches!(
+            KubeClientError::from(kube::Error::RequestSend),
+            KubeClientError::Connection(_)
+        ));
+    }
+
+    #[test]
+    fn test_prepare_new_manifest_fills_in_name_and_namespace() -> Result<(), anyhow::Error> {
+        let data = "apiVersion: apps/v1\nkind: Deployment\nspec: {}\n";
+
+        let (resource, o) =
+            prepare_new_manifest(&deployments(), "web.yaml", Some("default"), data)?;
+
+        assert_eq!(resource.api_version(), "apps/v1");
+        assert_eq!(o["metadata"]["name"], "web");
+        assert_eq!(o["metadata"]["namespace"], "default");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_entry_from_list_item() {
+        let item = json!({
+            "metadata": {
+                "name": "web",
+                "uid": "1234",
+                "resourceVersion": "42",
+                "creationTimestamp": "2020-06-01T10:00:00Z",
+                "managedFields": [
+                    { "manager": "kubectl", "time": "2020-06-01T10:00:00Z" },
+                    { "manager": "kube-controller-manager", "time": "2020-06-02T10:00:00Z" }
+                ]
+            },
+            "spec": {}
+        });
+
+        let view = ManifestView {
+            secret_view: SecretView::Encoded,
+            neat: false,
+            format: ManifestFormat::Yaml,
+        };
+        let cache = ObjectCache::new(Duration::from_secs(0));
+        let entry = object_entry(&deployments(), item, &view, &cache, "").unwrap();
+        let rendered = ManifestFormat::Yaml
+            .render(&json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {
+                    "name": "web",
+                    "uid": "1234",
+                    "resourceVersion": "42",
+                    "creationTimestamp": "2020-06-01T10:00:00Z",
+                    "managedFields": [
+                        { "manager": "kubectl", "time": "2020-06-01T10:00:00Z" },
+                        { "manager": "kube-controller-manager", "time": "2020-06-02T10:00:00Z" }
+                    ]
+                },
+                "spec": {}
+            }))
+            .unwrap();
+
+        assert_eq!(entry.name, "web");
+        assert_eq!(entry.stat.size, rendered.len() as u64);
+        assert_eq!(entry.stat.created, Some(1591005600));
+        assert_eq!(entry.stat.modified, Some(1591092000));
+        assert_eq!(entry.stat.resource_version.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn test_object_entry_size_matches_served_manifest() -> Result<(), anyhow::Error> {
+        let secrets = ApiResource {
+            group: String::new(),
+            version: String::from("v1"),
+            plural: String::from("secrets"),
+            kind: String::from("Secret"),
+            namespaced: true,
+        };
+        let item = json!({
+            "metadata": { "name": "db-creds", "uid": "1234", "resourceVersion": "42" },
+            "data": { "password": "aHVudGVyMg==" }
+        });
+
+        let view = ManifestView {
+            secret_view: SecretView::Redacted,
+            neat: true,
+            format: ManifestFormat::Json,
+        };
+        let served = ManifestFormat::Json.render(&json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "db-creds" },
+            "data": { "password": data::REDACTED }
+        }))?;
+
+        let cache = ObjectCache::new(Duration::from_secs(60));
+        let url = secrets.url(Some("default"), None);
+        cache.insert_list(&url, vec![item.clone()], "42");
+
+        let entry = object_entry(&secrets, item.clone(), &view, &cache, &url).unwrap();
+        assert_eq!(view.render(&secrets, item.clone())?, served);
+        assert_eq!(entry.stat.size, served.len() as u64);
+        assert_eq!(entry.stat.resource_version.as_deref(), Some("42"));
+
+        // Listed again unchanged, the object is not rendered again
+        assert_eq!(
+            cache.size(&url, "db-creds", "42", view.key()),
+            Some(served.len() as u64)
+        );
+        cache.set_size(&url, "db-creds", "42", view.key(), 1);
+        let entry = object_entry(&secrets, item.clone(), &view, &cache, &url).unwrap();
+        assert_eq!(entry.stat.size, 1);
+
+        let mut changed = item;
+        changed["metadata"]["resourceVersion"] = json!("43");
+        let entry = object_entry(&secrets, changed, &view, &cache, &url).unwrap();
+        assert_eq!(entry.stat.size, served.len() as u64);
 
         Ok(())
     }
+
+    #[test]
+    fn test_apply_request_leaves_out_server_fields() -> Result<(), anyhow::Error> {
+        let o = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "web",
+                "resourceVersion": "42",
+                "managedFields": [{ "manager": "kubectl" }]
+            },
+            "spec": { "replicas": 3 },
+            "status": { "replicas": 2 }
+        });
+
+        let request = apply_request(&deployments(), Some("default"), "web", o, false)?;
+
+        assert_eq!(request.method(), http::Method::PATCH);
+        assert_eq!(
+            request.uri(),
+            "/apis/apps/v1/namespaces/default/deployments/web?fieldManager=kubefs&force=false"
+        );
+        assert_eq!(
+            request.headers()[http::header::CONTENT_TYPE],
+            "application/apply-patch+yaml"
+        );
+        assert_eq!(
+            serde_json::from_slice::<Value>(request.body())?,
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": { "name": "web" },
+                "spec": { "replicas": 3 }
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_manifests_round_trip() -> Result<(), anyhow::Error> {
+        let o = json!({ "apiVersion": "v1", "kind": "ConfigMap", "data": { "a": "1" } });
+
+        let rendered = ManifestFormat::Json.render(&o)?;
+        assert!(rendered.starts_with("{\n  \"apiVersion\""));
+        assert_eq!(parse_manifest(&rendered)?, o);
+        assert_eq!(parse_manifest(&rendered.replace("  ", "\t"))?, o);
+        assert_eq!(parse_manifest(&ManifestFormat::Yaml.render(&o)?)?, o);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_new_manifest_rejects_other_kinds() {
+        let data = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: web\n";
+
+        assert!(prepare_new_manifest(&deployments(), "web", Some("default"), data).is_err());
+    }
+
+    #[test]
+    fn test_prepare_new_manifest_rejects_malformed_manifests() {
+        for data in &[
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata: foo\n",
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata: [web]\n",
+            "- apiVersion: apps/v1\n",
+            "web",
+        ] {
+            let e = prepare_new_manifest(&deployments(), "web", Some("default"), data).unwrap_err();
+            assert!(matches!(
+                e.downcast_ref(),
+                Some(KubeClientError::InvalidManifest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_prepare_new_manifest_rejects_other_namespaces() {
+        let data =
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: web\n  namespace: prod\n";
+
+        assert!(prepare_new_manifest(&deployments(), "web", Some("default"), data).is_err());
+    }
 }
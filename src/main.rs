@@ -1,15 +1,108 @@
+mod cache;
+mod data;
 mod fs;
 mod inode;
 mod kube_client;
+mod neat;
+mod overlay;
+mod pod;
 
 use clap::{App, Arg};
+use data::SecretView;
 use fs::KubeFS;
-use kube_client::KubeClient;
-use std::ffi::OsStr;
+use inode::{DeleteOptions, K8sInteractions, KubeFSINodes, NamespaceFilter};
+use kube_client::{kubeconfig_contexts, ConnectOptions, KubeClient, ManifestFormat, WriteMode};
+use std::{collections::HashMap, ffi::OsStr, path::PathBuf, process, time::Duration};
+
+/// How long a listing stays cached without being used.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long a single call to the API server may take before it fails.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `-o` values split into the options understood by KubeFS and the ones
+/// passed on to FUSE.
+struct MountOptions {
+    delete_options: DeleteOptions,
+    request_timeout: Duration,
+    fuse_options: Vec<String>,
+}
+
+fn parse_mount_options<'a>(values: impl Iterator<Item = &'a str>) -> Result<MountOptions, String> {
+    let mut delete_options = DeleteOptions::default();
+    let mut request_timeout = DEFAULT_REQUEST_TIMEOUT;
+    let mut fuse_options = Vec::new();
+
+    for option in values.flat_map(|v| v.split(',')).filter(|o| !o.is_empty()) {
+        let mut parts = option.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("propagation"), Some(policy)) => {
+                let policy = match policy.to_lowercase().as_str() {
+                    "foreground" => "Foreground",
+                    "background" => "Background",
+                    "orphan" => "Orphan",
+                    _ => return Err(format!("Unknown propagation policy {}", policy)),
+                };
+                delete_options.propagation_policy = Some(String::from(policy));
+            }
+            (Some("grace_period"), Some(seconds)) => {
+                let seconds = seconds
+                    .parse()
+                    .map_err(|_| format!("Invalid grace period {}", seconds))?;
+                delete_options.grace_period_seconds = Some(seconds);
+            }
+            (Some("timeout"), Some(seconds)) => {
+                let seconds = seconds
+                    .parse()
+                    .ok()
+                    .filter(|seconds| *seconds > 0)
+                    .ok_or_else(|| format!("Invalid timeout {}", seconds))?;
+                request_timeout = Duration::from_secs(seconds);
+            }
+            _ => fuse_options.push(option.to_string()),
+        }
+    }
+
+    Ok(MountOptions {
+        delete_options,
+        request_timeout,
+        fuse_options,
+    })
+}
+
+/// Directory a context is mounted under. Context names such as EKS cluster
+/// ARNs can contain slashes, which cannot appear in a file name.
+fn context_directory(context: &str) -> String {
+    context.replace('/', "_")
+}
+
+/// Directories for the contexts to mount. Fails when two contexts would be
+/// mounted under the same directory, as one would hide the other.
+fn context_directories(contexts: &[String]) -> Result<Vec<String>, String> {
+    let mut mounted: HashMap<String, &str> = HashMap::new();
+
+    contexts
+        .iter()
+        .map(|context| {
+            let directory = context_directory(context);
+            match mounted.insert(directory.clone(), context) {
+                Some(other) => Err(format!(
+                    "Contexts {} and {} would both be mounted as {}",
+                    other, context, directory
+                )),
+                None => Ok(directory),
+            }
+        })
+        .collect()
+}
+
+fn exit_with_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("{}", e);
+    process::exit(1);
+}
 
 fn main() {
     env_logger::init();
-    let kube = KubeClient::new();
 
     // Parse command line arguments
     let matches = App::new("KubeFS")
@@ -21,20 +114,239 @@ fn main() {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("options")
+                .help("Mount options: propagation=<foreground|background|orphan>, grace_period=<seconds>, timeout=<seconds>")
+                .short("o")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("kubeconfig")
+                .help("Path to the kubeconfig file to use")
+                .long("kubeconfig")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .help("The kubeconfig context to use")
+                .long("context")
+                .takes_value(true)
+                .conflicts_with_all(&["contexts", "all-contexts"]),
+        )
+        .arg(
+            Arg::with_name("as")
+                .help("Username to impersonate")
+                .long("as")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("as-group")
+                .help("Group to impersonate, can be repeated")
+                .long("as-group")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("namespaces")
+                .help("Only show these namespaces, * matches any characters")
+                .long("namespaces")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("exclude-namespaces")
+                .help("Hide these namespaces, * matches any characters")
+                .long("exclude-namespaces")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .help("Reject every change to the cluster")
+                .long("read-only"),
+        )
+        .arg(
+            Arg::with_name("secrets")
+                .help("How Secret values are shown: encoded as stored, redacted, or decoded into stringData")
+                .long("secrets")
+                .takes_value(true)
+                .possible_values(&["encoded", "redacted", "decoded"])
+                .default_value("encoded"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("Format manifests are served in, writes are accepted in either")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["yaml", "json"])
+                .default_value("yaml"),
+        )
+        .arg(
+            Arg::with_name("write-mode")
+                .help("How edits are written: replace the object, or server-side apply as field manager kubefs")
+                .long("write-mode")
+                .takes_value(true)
+                .possible_values(&["replace", "apply"])
+                .default_value("replace"),
+        )
+        .arg(
+            Arg::with_name("force-conflicts")
+                .help("Take over fields owned by other managers when applying")
+                .long("force-conflicts"),
+        )
+        .arg(
+            Arg::with_name("neat")
+                .help("Leave status and server-populated metadata out of manifests")
+                .long("neat"),
+        )
+        .arg(
+            Arg::with_name("cache-ttl")
+                .help("Seconds an unused listing stays cached, 0 disables the cache")
+                .long("cache-ttl")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("contexts")
+                .help("Mount these kubeconfig contexts, each as a top-level directory")
+                .long("contexts")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("all-contexts")
+                .help("Mount every kubeconfig context, each as a top-level directory")
+                .long("all-contexts")
+                .conflicts_with("contexts"),
+        )
         .get_matches();
 
     let mount_path = matches
         .value_of("mountpath")
         .expect("Mount path is a required parameter");
 
-    let options = ["-o", "wro", "-o", "fsname=kubefs", "-o", "auto_unmount"]
+    let mount_options =
+        match parse_mount_options(matches.values_of("options").into_iter().flatten()) {
+            Ok(options) => options,
+            Err(e) => exit_with_error(e),
+        };
+
+    let mut options = ["-o", "wro", "-o", "fsname=kubefs", "-o", "auto_unmount"]
         .iter()
         .map(|o| o.as_ref())
         .collect::<Vec<&OsStr>>();
+    for option in &mount_options.fuse_options {
+        options.push("-o".as_ref());
+        options.push(option.as_ref());
+    }
 
     println!("Mounting to location {}", mount_path);
 
-    let fs = KubeFS::new(kube);
+    let cache_ttl = match matches.value_of("cache-ttl") {
+        Some(seconds) => Duration::from_secs(
+            seconds
+                .parse()
+                .unwrap_or_else(|_| exit_with_error(format!("Invalid cache TTL {}", seconds))),
+        ),
+        None => DEFAULT_CACHE_TTL,
+    };
+
+    let connect_options = ConnectOptions {
+        kubeconfig: matches.value_of("kubeconfig").map(PathBuf::from),
+        context: matches.value_of("context").map(String::from),
+        impersonate_user: matches.value_of("as").map(String::from),
+        impersonate_groups: matches
+            .values_of("as-group")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+    };
+
+    let namespace_filter = NamespaceFilter {
+        include: matches
+            .values_of("namespaces")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+        exclude: matches
+            .values_of("exclude-namespaces")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+    };
+
+    let secret_view: SecretView = matches
+        .value_of("secrets")
+        .unwrap_or_default()
+        .parse()
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    let format: ManifestFormat = matches
+        .value_of("format")
+        .unwrap_or_default()
+        .parse()
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    let write_mode = match matches.value_of("write-mode") {
+        Some("apply") => WriteMode::Apply {
+            force: matches.is_present("force-conflicts"),
+        },
+        _ => WriteMode::Replace,
+    };
+
+    let connect = |context: Option<&str>| {
+        let options = ConnectOptions {
+            context: context
+                .map(String::from)
+                .or(connect_options.context.clone()),
+            ..connect_options.clone()
+        };
+
+        KubeClient::new(&options, cache_ttl, mount_options.request_timeout).map(|client| {
+            client
+                .with_secret_view(secret_view)
+                .with_neat(matches.is_present("neat"))
+                .with_format(format)
+                .with_write_mode(write_mode)
+        })
+    };
+
+    let inodes = if matches.is_present("all-contexts") || matches.is_present("contexts") {
+        let contexts: Vec<String> = match matches.values_of("contexts") {
+            Some(contexts) => contexts.map(String::from).collect(),
+            None => kubeconfig_contexts(&connect_options).unwrap_or_else(|e| exit_with_error(e)),
+        };
+
+        let directories = context_directories(&contexts).unwrap_or_else(|e| exit_with_error(e));
+
+        let mut clients: Vec<(String, Box<dyn K8sInteractions>)> = vec![];
+        for (context, directory) in contexts.into_iter().zip(directories) {
+            match connect(Some(&context)) {
+                Ok(client) => clients.push((directory, Box::new(client))),
+                // One broken context should not keep the others from mounting
+                Err(e) if matches.is_present("all-contexts") => {
+                    eprintln!("Skipping context {}: {}", context, e)
+                }
+                Err(e) => exit_with_error(format!("Context {}: {}", context, e)),
+            }
+        }
+
+        KubeFSINodes::with_contexts(clients)
+    } else {
+        KubeFSINodes::new(Box::new(
+            connect(None).unwrap_or_else(|e| exit_with_error(e)),
+        ))
+    };
+
+    let inodes = inodes
+        .with_delete_options(mount_options.delete_options)
+        .with_namespace_filter(namespace_filter);
+    let fs = KubeFS::new(inodes).with_read_only(matches.is_present("read-only"));
 
     fuse::mount(fs, &mount_path, &options).unwrap();
 }
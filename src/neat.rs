@@ -0,0 +1,171 @@
+use serde_json::{Map, Value};
+
+/// Fields of `metadata` which the API server fills in and keeps up to date.
+const SERVER_METADATA: [&str; 6] = [
+    "managedFields",
+    "resourceVersion",
+    "uid",
+    "creationTimestamp",
+    "generation",
+    "selfLink",
+];
+
+/// Annotation `kubectl apply` keeps a copy of the whole manifest in.
+pub const LAST_APPLIED_ANNOTATION: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// Strips what the API server populates from an object, leaving what was
+/// written to it.
+pub fn strip(o: &mut Value) {
+    if let Some(object) = o.as_object_mut() {
+        object.remove("status");
+    }
+
+    let metadata = match o.get_mut("metadata").and_then(Value::as_object_mut) {
+        Some(metadata) => metadata,
+        None => return,
+    };
+    for field in &SERVER_METADATA {
+        metadata.remove(*field);
+    }
+
+    let annotations = metadata
+        .get_mut("annotations")
+        .and_then(Value::as_object_mut);
+    if let Some(annotations) = annotations {
+        annotations.remove(LAST_APPLIED_ANNOTATION);
+        if annotations.is_empty() {
+            metadata.remove("annotations");
+        }
+    }
+}
+
+/// The mapping under `field`, added when it is left out. Fails when the
+/// field holds anything else.
+fn mapping<'a>(
+    o: &'a mut Map<String, Value>,
+    field: &str,
+) -> anyhow::Result<&'a mut Map<String, Value>> {
+    o.entry(field)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a mapping", field))
+}
+
+/// Puts the fields `strip` removed back into an edited object from the one
+/// stored, so the edit is sent as a complete object. Fields the edit sets
+/// itself are kept. The resourceVersion is the one the edit was `read_at`
+/// when known, so the update is refused if the object changed since. Fails
+/// when the edit is not shaped like an object.
+pub fn restore(edited: &mut Value, current: &Value, read_at: Option<&str>) -> anyhow::Result<()> {
+    let edited = edited
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("manifest is not a mapping"))?;
+
+    if let Some(status) = current.get("status") {
+        edited.entry("status").or_insert_with(|| status.clone());
+    }
+
+    let metadata = mapping(edited, "metadata")?;
+    if let Some(read_at) = read_at {
+        metadata
+            .entry("resourceVersion")
+            .or_insert_with(|| read_at.into());
+    }
+    for field in &SERVER_METADATA {
+        if let Some(value) = current["metadata"].get(*field) {
+            metadata.entry(*field).or_insert_with(|| value.clone());
+        }
+    }
+
+    let last_applied = &current["metadata"]["annotations"][LAST_APPLIED_ANNOTATION];
+    if !last_applied.is_null() {
+        mapping(metadata, "annotations")?
+            .entry(LAST_APPLIED_ANNOTATION)
+            .or_insert_with(|| last_applied.clone());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_strip_and_restore_round_trip() {
+        let stored = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "name": "settings",
+                "uid": "1234",
+                "resourceVersion": "42",
+                "creationTimestamp": "2020-05-01T10:00:00Z",
+                "managedFields": [{ "manager": "kubectl" }],
+                "annotations": { LAST_APPLIED_ANNOTATION: "{}" }
+            },
+            "data": { "debug": "false" }
+        });
+
+        let mut neat = stored.clone();
+        strip(&mut neat);
+        assert_eq!(
+            neat,
+            json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": "settings" },
+                "data": { "debug": "false" }
+            })
+        );
+
+        neat["data"]["debug"] = json!("true");
+        restore(&mut neat, &stored, None).unwrap();
+
+        let mut expected = stored;
+        expected["data"]["debug"] = json!("true");
+        assert_eq!(neat, expected);
+    }
+
+    #[test]
+    fn test_restore_keeps_version_read() {
+        let stored = json!({
+            "metadata": { "name": "settings", "uid": "1234", "resourceVersion": "43" },
+            "data": { "debug": "false" }
+        });
+
+        // The object was changed by someone else after it was read at 42
+        let mut edited = json!({
+            "metadata": { "name": "settings" },
+            "data": { "debug": "true" }
+        });
+        restore(&mut edited, &stored, Some("42")).unwrap();
+
+        assert_eq!(edited["metadata"]["resourceVersion"], "42");
+        assert_eq!(edited["metadata"]["uid"], "1234");
+    }
+
+    #[test]
+    fn test_restore_rejects_malformed_manifests() {
+        let stored = json!({
+            "metadata": {
+                "name": "settings",
+                "resourceVersion": "42",
+                "annotations": { LAST_APPLIED_ANNOTATION: "{}" }
+            },
+            "status": {}
+        });
+
+        for edited in &[
+            json!("settings"),
+            json!(["settings"]),
+            json!({ "metadata": "settings" }),
+            json!({ "metadata": { "annotations": ["team"] } }),
+        ] {
+            let mut edited = edited.clone();
+            strip(&mut edited);
+            assert!(restore(&mut edited, &stored, None).is_err());
+        }
+    }
+}
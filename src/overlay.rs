@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+/// A file which only exists in the mount, such as an editor's swap, backup or
+/// temporary file. It is never sent to the API server unless it is renamed
+/// over an object file.
+pub struct ScratchFile {
+    pub parent: u64,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// In-memory files layered over the Kubernetes tree, keyed by inode number.
+pub struct Overlay {
+    files: HashMap<u64, ScratchFile>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, ino: u64, parent: u64, name: &str) {
+        self.files.insert(
+            ino,
+            ScratchFile {
+                parent,
+                name: name.to_string(),
+                data: vec![],
+            },
+        );
+    }
+
+    pub fn get(&self, ino: &u64) -> Option<&ScratchFile> {
+        self.files.get(ino)
+    }
+
+    pub fn get_mut(&mut self, ino: &u64) -> Option<&mut ScratchFile> {
+        self.files.get_mut(ino)
+    }
+
+    pub fn contains(&self, ino: &u64) -> bool {
+        self.files.contains_key(ino)
+    }
+
+    pub fn find(&self, parent: &u64, name: &str) -> Option<u64> {
+        self.files
+            .iter()
+            .find(|(_, f)| f.parent == *parent && f.name == name)
+            .map(|(ino, _)| *ino)
+    }
+
+    pub fn remove(&mut self, ino: &u64) -> Option<ScratchFile> {
+        self.files.remove(ino)
+    }
+
+    pub fn rename(&mut self, ino: &u64, parent: &u64, name: &str) {
+        if let Some(file) = self.files.get_mut(ino) {
+            file.parent = *parent;
+            file.name = name.to_string();
+        }
+    }
+
+    /// Scratch files in a directory, sorted by name so listings are stable.
+    pub fn children(&self, parent: &u64) -> Vec<(u64, &ScratchFile)> {
+        let mut children: Vec<(u64, &ScratchFile)> = self
+            .files
+            .iter()
+            .filter(|(_, f)| f.parent == *parent)
+            .map(|(ino, f)| (*ino, f))
+            .collect();
+        children.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+
+        children
+    }
+}
+
+/// Whether a newly created file in a kind directory is scratch rather than a
+/// manifest for a new object. Covers hidden files (vim and kate swap files,
+/// emacs lock files, gedit temporaries), backups (`name~`), emacs auto-saves
+/// (`#name#`), vim's `4913` write probe and `sed -i` temporaries. Any other
+/// name is taken as a manifest, so a file named unlike its object still
+/// creates it, named after `metadata.name`, or fails.
+pub fn is_scratch_name(name: &str) -> bool {
+    const SUFFIXES: [&str; 5] = [".swp", ".swx", ".swo", ".tmp", ".bak"];
+
+    let sed_temp = name.len() == 9
+        && name.starts_with("sed")
+        && name[3..].chars().all(|c| c.is_ascii_alphanumeric());
+
+    name.starts_with('.')
+        || name.ends_with('~')
+        || (name.len() > 1 && name.starts_with('#') && name.ends_with('#'))
+        || name == "4913"
+        || sed_temp
+        || SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_scratch_name() {
+        for name in &[
+            ".web.yaml.swp",
+            "web.yaml~",
+            "#web.yaml#",
+            ".#web.yaml",
+            "4913",
+            "sedAb3xYz",
+            "web.yaml.tmp",
+        ] {
+            assert!(is_scratch_name(name), "{} should be scratch", name);
+        }
+
+        for name in &["web.yaml", "web", "nginx-7d9f.json", "My_App.yaml"] {
+            assert!(!is_scratch_name(name), "{} should not be scratch", name);
+        }
+    }
+
+    #[test]
+    fn test_find_follows_rename() {
+        let mut overlay = Overlay::new();
+        overlay.insert(10, 2, ".web.yaml.swp");
+
+        assert_eq!(overlay.find(&2, ".web.yaml.swp"), Some(10));
+
+        overlay.rename(&10, &3, "web.yaml~");
+
+        assert_eq!(overlay.find(&2, ".web.yaml.swp"), None);
+        assert_eq!(overlay.find(&3, "web.yaml~"), Some(10));
+        assert_eq!(overlay.children(&3).len(), 1);
+    }
+}
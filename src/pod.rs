@@ -0,0 +1,422 @@
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard,
+};
+
+/// Suffix of the file holding the log of the previous instance of a
+/// container. Container names are DNS labels, so they never contain a dot.
+pub const PREVIOUS_LOG_SUFFIX: &str = ".previous";
+
+/// Files in the directory of each container, one value per file.
+pub const CONTAINER_FILES: [&str; 7] = [
+    "image",
+    "state",
+    "ready",
+    "restartCount",
+    "lastTerminationReason",
+    "requests",
+    "limits",
+];
+
+const CONTAINER_FIELDS: [&str; 3] = ["initContainers", "containers", "ephemeralContainers"];
+
+const STATUS_FIELDS: [&str; 3] = [
+    "initContainerStatuses",
+    "containerStatuses",
+    "ephemeralContainerStatuses",
+];
+
+/// Names of the containers of a pod, init containers first.
+pub fn container_names(pod: &Value) -> Vec<String> {
+    CONTAINER_FIELDS
+        .iter()
+        .filter_map(|field| pod["spec"][field].as_array())
+        .flatten()
+        .filter_map(|container| container["name"].as_str().map(String::from))
+        .collect()
+}
+
+fn find_by_name<'a>(pod: &'a Value, section: &str, fields: &[&str], name: &str) -> &'a Value {
+    fields
+        .iter()
+        .filter_map(|field| pod[section][field].as_array())
+        .flatten()
+        .find(|container| container["name"] == name)
+        .unwrap_or(&Value::Null)
+}
+
+/// Describes a container state the way `kubectl describe` does, e.g.
+/// `waiting: CrashLoopBackOff` or `terminated: Error (exit code 1)`.
+fn describe_state(state: &Value) -> String {
+    let (name, details) = match state.as_object().and_then(|state| state.iter().next()) {
+        Some(state) => state,
+        None => return String::new(),
+    };
+
+    match (details["reason"].as_str(), details["exitCode"].as_i64()) {
+        (Some(reason), Some(code)) => format!("{}: {} (exit code {})", name, reason, code),
+        (None, Some(code)) => format!("{} (exit code {})", name, code),
+        (Some(reason), None) => format!("{}: {}", name, reason),
+        (None, None) => name.clone(),
+    }
+}
+
+/// Renders one resource per line as `<resource>: <quantity>`.
+fn describe_resources(resources: &Value) -> String {
+    resources
+        .as_object()
+        .map(|resources| {
+            resources
+                .iter()
+                .map(|(name, quantity)| {
+                    format!("{}: {}\n", name, quantity.as_str().unwrap_or_default())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Contents of a file in the directory of a container, or `None` when the
+/// pod has no such container.
+pub fn container_file(pod: &Value, container: &str, file: &str) -> Option<String> {
+    let spec = find_by_name(pod, "spec", &CONTAINER_FIELDS, container);
+    if spec.is_null() {
+        return None;
+    }
+    let status = find_by_name(pod, "status", &STATUS_FIELDS, container);
+
+    let value = match file {
+        "image" => spec["image"].as_str().unwrap_or_default().to_string(),
+        "state" => describe_state(&status["state"]),
+        "ready" => status["ready"].as_bool().unwrap_or(false).to_string(),
+        "restartCount" => status["restartCount"].as_u64().unwrap_or(0).to_string(),
+        "lastTerminationReason" => status["lastState"]["terminated"]["reason"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        "requests" | "limits" => return Some(describe_resources(&spec["resources"][file])),
+        _ => return None,
+    };
+
+    if value.is_empty() {
+        Some(value)
+    } else {
+        Some(value + "\n")
+    }
+}
+
+/// Files in the `logs` directory of a pod: one per container, plus one for
+/// the previous instance of every container which has been restarted.
+pub fn log_files(pod: &Value) -> Vec<String> {
+    let restarted: Vec<&str> = STATUS_FIELDS
+        .iter()
+        .filter_map(|field| pod["status"][field].as_array())
+        .flatten()
+        .filter(|status| status["lastState"]["terminated"].is_object())
+        .filter_map(|status| status["name"].as_str())
+        .collect();
+
+    let mut files = vec![];
+    for name in container_names(pod) {
+        if restarted.contains(&name.as_str()) {
+            files.push(format!("{}{}", name, PREVIOUS_LOG_SUFFIX));
+        }
+        files.push(name);
+    }
+
+    files
+}
+
+/// Splits a log file name into the container and whether it is the log of
+/// the previous instance.
+pub fn parse_log_file(name: &str) -> (&str, bool) {
+    match name.strip_suffix(PREVIOUS_LOG_SUFFIX) {
+        Some(container) => (container, true),
+        None => (name, false),
+    }
+}
+
+/// Lists the directory given as `$1` one entry per line, with a `/` after
+/// directories, like `ls -1Ap` but also marking symlinks to directories.
+/// `ls -L` would mark them too, but fails the whole listing on a dangling
+/// symlink. Only needs a POSIX `sh` in the container.
+pub const LIST_DIRECTORY: &str = r#"cd -- "$1" || exit
+for f in .* *; do
+    case "$f" in .|..) continue ;; esac
+    if [ -d "$f" ]; then echo "$f/"; elif [ -e "$f" ] || [ -L "$f" ]; then echo "$f"; fi
+done"#;
+
+/// Parses the output of `LIST_DIRECTORY` into names and whether each one is
+/// a directory.
+pub fn parse_listing(output: &str) -> Vec<(String, bool)> {
+    output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.strip_suffix('/') {
+            Some(name) => (name.to_string(), true),
+            None => (line.to_string(), false),
+        })
+        .collect()
+}
+
+/// A log timestamp as seconds and nanoseconds. The API server trims
+/// trailing zeros from the fraction, so the text does not sort by itself.
+type LogTimestamp = (String, u32);
+
+fn parse_timestamp(timestamp: &str) -> Option<LogTimestamp> {
+    let timestamp = timestamp.strip_suffix('Z')?;
+    let (seconds, fraction) = match timestamp.find('.') {
+        Some(i) => (&timestamp[..i], &timestamp[i + 1..]),
+        None => (timestamp, ""),
+    };
+
+    let nanos = format!("{:0<9}", fraction).get(..9)?.parse().ok()?;
+
+    Some((seconds.to_string(), nanos))
+}
+
+struct LogState {
+    data: Vec<u8>,
+    modified: i64,
+    partial: Vec<u8>,
+    last: Option<LogTimestamp>,
+    /// Why following stopped or is being retried, until it is reported
+    error: Option<anyhow::Error>,
+}
+
+/// The log of a container as seen by one open handle. It starts out with
+/// the output so far and is appended to by a follow stream until the handle
+/// is released, which is how `tail -f` sees new lines.
+pub struct FollowedLog {
+    state: Mutex<LogState>,
+    closed: AtomicBool,
+}
+
+impl FollowedLog {
+    pub fn new() -> Self {
+        FollowedLog {
+            state: Mutex::new(LogState {
+                data: vec![],
+                modified: time::get_time().sec,
+                partial: vec![],
+                last: None,
+                error: None,
+            }),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LogState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Appends output requested with `timestamps=true`. The timestamps are
+    /// stripped, and lines no newer than the last one appended are dropped,
+    /// as a follow stream resumed with `sinceTime` repeats the lines of the
+    /// second it starts in.
+    pub fn append(&self, chunk: &[u8]) {
+        let mut state = self.state();
+        state.partial.extend_from_slice(chunk);
+
+        while let Some(end) = state.partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = state.partial.drain(..=end).collect();
+            let split = line.iter().position(|b| *b == b' ');
+            let timestamp = split
+                .and_then(|i| std::str::from_utf8(&line[..i]).ok())
+                .and_then(parse_timestamp);
+
+            match (timestamp, split) {
+                (Some(timestamp), Some(i)) => {
+                    if state.last.as_ref().is_some_and(|last| timestamp <= *last) {
+                        continue;
+                    }
+                    state.data.extend_from_slice(&line[i + 1..]);
+                    state.last = Some(timestamp);
+                }
+                _ => state.data.extend_from_slice(&line),
+            }
+
+            state.modified = time::get_time().sec;
+        }
+    }
+
+    /// Drops the unfinished line of a stream which ended. The stream resumed
+    /// with `sinceTime` sends that line again in full.
+    pub fn resume(&self) {
+        self.state().partial.clear();
+    }
+
+    /// Notes a failure to follow, to be reported with `take_error` rather
+    /// than mixed into the output of the container.
+    pub fn fail(&self, error: anyhow::Error) {
+        self.state().error = Some(error);
+    }
+
+    pub fn take_error(&self) -> Option<anyhow::Error> {
+        self.state().error.take()
+    }
+
+    /// `sinceTime` to resume following from, the second of the last line.
+    pub fn since_time(&self) -> Option<String> {
+        self.state()
+            .last
+            .as_ref()
+            .map(|(seconds, _)| format!("{}Z", seconds))
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(&self.state().data)
+    }
+
+    /// Size and modification time, for `getattr`.
+    pub fn stat(&self) -> (u64, i64) {
+        let state = self.state();
+
+        (state.data.len() as u64, state.modified)
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_log_files_include_previous_of_restarted_containers() {
+        let pod = json!({
+            "spec": {
+                "initContainers": [{ "name": "migrate" }],
+                "containers": [{ "name": "web" }, { "name": "proxy" }]
+            },
+            "status": {
+                "containerStatuses": [
+                    { "name": "web", "lastState": { "terminated": { "exitCode": 1 } } },
+                    { "name": "proxy", "lastState": {} }
+                ]
+            }
+        });
+
+        assert_eq!(
+            log_files(&pod),
+            vec!["migrate", "web.previous", "web", "proxy"]
+        );
+        assert_eq!(parse_log_file("web.previous"), ("web", true));
+        assert_eq!(parse_log_file("web"), ("web", false));
+    }
+
+    #[test]
+    fn test_parse_listing() {
+        assert_eq!(
+            parse_listing("bin/\n.profile\nhosts\n"),
+            vec![
+                (String::from("bin"), true),
+                (String::from(".profile"), false),
+                (String::from("hosts"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_marks_symlinked_directories() -> Result<(), anyhow::Error> {
+        let root = std::env::temp_dir().join(format!("kubefs-listing-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("etc"))?;
+        std::fs::write(root.join(".profile"), "")?;
+        std::os::unix::fs::symlink("etc", root.join("config"))?;
+        std::os::unix::fs::symlink("missing", root.join("dangling"))?;
+
+        let output = std::process::Command::new("sh")
+            .args(["-c", LIST_DIRECTORY, "sh"])
+            .arg(&root)
+            .output()?;
+        std::fs::remove_dir_all(&root)?;
+
+        assert!(output.status.success());
+        let mut listing = parse_listing(&String::from_utf8(output.stdout)?);
+        listing.sort();
+        assert_eq!(
+            listing,
+            vec![
+                (String::from(".profile"), false),
+                (String::from("config"), true),
+                (String::from("dangling"), false),
+                (String::from("etc"), true),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_files() {
+        let pod = json!({
+            "spec": {
+                "containers": [{
+                    "name": "web",
+                    "image": "nginx:1.19",
+                    "resources": { "requests": { "cpu": "100m", "memory": "64Mi" } }
+                }]
+            },
+            "status": {
+                "containerStatuses": [{
+                    "name": "web",
+                    "ready": false,
+                    "restartCount": 3,
+                    "state": { "waiting": { "reason": "CrashLoopBackOff" } },
+                    "lastState": { "terminated": { "reason": "OOMKilled", "exitCode": 137 } }
+                }]
+            }
+        });
+        let file = |name| container_file(&pod, "web", name).unwrap();
+
+        assert_eq!(file("image"), "nginx:1.19\n");
+        assert_eq!(file("state"), "waiting: CrashLoopBackOff\n");
+        assert_eq!(file("ready"), "false\n");
+        assert_eq!(file("restartCount"), "3\n");
+        assert_eq!(file("lastTerminationReason"), "OOMKilled\n");
+        assert_eq!(file("requests"), "cpu: 100m\nmemory: 64Mi\n");
+        assert_eq!(file("limits"), "");
+        assert!(container_file(&pod, "proxy", "image").is_none());
+        assert_eq!(
+            describe_state(&json!({ "terminated": { "reason": "Error", "exitCode": 1 } })),
+            "terminated: Error (exit code 1)"
+        );
+    }
+
+    #[test]
+    fn test_followed_log_strips_timestamps_and_skips_repeated_lines() {
+        let log = FollowedLog::new();
+
+        log.append(b"2020-05-01T10:00:00.5Z first\n2020-05-01T10:00:01.25Z sec");
+        log.append(b"ond\n");
+        assert_eq!(log.since_time().as_deref(), Some("2020-05-01T10:00:01Z"));
+
+        // A resumed stream starts over at the beginning of the second
+        log.append(b"2020-05-01T10:00:01.1Z old\n2020-05-01T10:00:01.25Z second\n");
+        log.append(b"2020-05-01T10:00:01.3Z third\n");
+
+        assert_eq!(log.read(|data| data.to_vec()), b"first\nsecond\nthird\n");
+        assert_eq!(log.stat().0, 19);
+    }
+
+    #[test]
+    fn test_followed_log_drops_unfinished_line_on_resume() {
+        let log = FollowedLog::new();
+
+        log.append(b"2020-05-01T10:00:00.5Z first\n2020-05-01T10:00:01.25Z sec");
+        log.resume();
+        log.append(b"{\"kind\":\"Status\",\"code\":500}");
+        log.resume();
+        log.append(b"2020-05-01T10:00:01.25Z second\n");
+
+        assert_eq!(log.read(|data| data.to_vec()), b"first\nsecond\n");
+    }
+}